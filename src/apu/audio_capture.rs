@@ -0,0 +1,132 @@
+use crate::apu::wav_writer::WavWriter;
+use crate::apu::CLOCK_RATE;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Suffix of the file of each channel recorded separately, in the order of `Apu::channel_samples`
+const CHANNEL_NAMES: [&str; 6] = ["square1", "square2", "wave", "noise", "fifo_a", "fifo_b"];
+
+/// audio_capture::AudioCapture
+///
+/// Records the output of the mixer to a stereo WAV file, and optionally each channel to a mono
+/// file named after the main one (`music.wav` gives `music-square1.wav` ... `music-fifo_b.wav`).
+/// Samples are written before any resampling or volume control, so that recordings of the same
+/// run are identical. The rate of the files is the one of the mixer when the recording starts; if
+/// a game changes it afterwards, samples are repeated or dropped to keep the timing.
+pub struct AudioCapture {
+    mixed: WavWriter,
+    channels: Vec<WavWriter>,
+    sample_rate: u32,
+    elapsed_cycles: u64, // Cycles covered by the samples received
+    frames: u64,         // Frames written to the files
+}
+
+impl AudioCapture {
+    /// AudioCapture::create
+    ///
+    /// @param path [&Path]: path of the file of the mixed output
+    /// @param per_channel [bool]: true to also record each channel separately
+    /// @param sample_rate [u32]: rate of the files, in Hz
+    /// @return [io::Result<AudioCapture>]: capture, or the error creating a file
+    pub fn create(path: &Path, per_channel: bool, sample_rate: u32) -> io::Result<Self> {
+        let mixed = WavWriter::create(path, 2, sample_rate)?;
+        let channels = if per_channel {
+            CHANNEL_NAMES
+                .iter()
+                .map(|name| WavWriter::create(&Self::channel_path(path, name), 1, sample_rate))
+                .collect::<io::Result<Vec<WavWriter>>>()?
+        } else {
+            Vec::new()
+        };
+
+        Ok(Self {
+            mixed,
+            channels,
+            sample_rate,
+            elapsed_cycles: 0,
+            frames: 0,
+        })
+    }
+
+    /// AudioCapture::channel_path
+    ///
+    /// @param path [&Path]: path of the file of the mixed output
+    /// @param name [&str]: name of the channel
+    /// @return [PathBuf]: path of the file of the channel
+    fn channel_path(path: &Path, name: &str) -> PathBuf {
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        path.with_file_name(format!("{}-{}.wav", stem, name))
+    }
+
+    /// AudioCapture::push
+    ///
+    /// @param mixed [(i16, i16)]: left and right output of the mixer
+    /// @param channels [[i16; 6]]: output of each channel
+    /// @param input_rate [u32]: rate of the samples of the mixer, in Hz
+    /// @return [io::Result<()>]: error writing the files, if any
+    pub fn push(
+        &mut self,
+        mixed: (i16, i16),
+        channels: [i16; 6],
+        input_rate: u32,
+    ) -> io::Result<()> {
+        self.elapsed_cycles += (CLOCK_RATE / input_rate) as u64;
+
+        let period = (CLOCK_RATE / self.sample_rate) as u64;
+        while (self.frames + 1) * period <= self.elapsed_cycles {
+            self.mixed.write_frame(&[mixed.0, mixed.1])?;
+            for (wav, sample) in self.channels.iter_mut().zip(channels) {
+                wav.write_frame(&[sample])?;
+            }
+            self.frames += 1;
+        }
+        Ok(())
+    }
+
+    /// AudioCapture::finish
+    ///
+    /// Complete the headers of the files and close them.
+    ///
+    /// @return [io::Result<()>]: error writing the files, if any
+    pub fn finish(self) -> io::Result<()> {
+        self.mixed.finish()?;
+        self.channels.into_iter().try_for_each(WavWriter::finish)
+    }
+}
+
+#[cfg(test)]
+mod test_audio_capture {
+
+    use crate::apu::audio_capture::AudioCapture;
+
+    #[test]
+    fn test_capture() {
+        let dir = std::env::temp_dir().join("crusty_gba_test_audio_capture");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("music.wav");
+
+        let mut capture = AudioCapture::create(&path, true, 32768).unwrap();
+        capture.push((1, 2), [3, 4, 5, 6, 7, 8], 32768).unwrap();
+
+        // Faster samples are dropped, slower ones repeated
+        (0..4).for_each(|i| capture.push((10 + i, 0), [0; 6], 131072).unwrap());
+        capture.push((20, 0), [0, 0, 0, 0, 0, 9], 16384).unwrap();
+        capture.finish().unwrap();
+
+        let mixed = std::fs::read(&path).unwrap();
+        let samples: Vec<i16> = mixed[44..]
+            .chunks(2)
+            .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]))
+            .collect();
+        assert_eq!(samples, vec![1, 2, 13, 0, 20, 0, 20, 0]);
+
+        let square1 = std::fs::read(dir.join("music-square1.wav")).unwrap();
+        assert_eq!(square1[22..24], 1_u16.to_le_bytes());
+        assert_eq!(square1[44..], [3, 0, 0, 0, 0, 0, 0, 0]);
+        let fifo_b = std::fs::read(dir.join("music-fifo_b.wav")).unwrap();
+        assert_eq!(fifo_b[44..], [8, 0, 0, 0, 9, 0, 9, 0]);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,126 @@
+use crate::apu::resampler::Resampler;
+use sdl2::audio::{AudioQueue, AudioSpecDesired};
+use std::thread;
+use std::time::Duration;
+
+/// Rate requested to the audio device of the host, in Hz
+const OUTPUT_RATE: i32 = 48000;
+/// Frames kept in the queue of the device, about 50ms
+const TARGET_QUEUED_FRAMES: u32 = 2400;
+/// Largest relative change of the resampling ratio used to keep the queue at its target
+const MAX_RATE_ADJUST: f64 = 0.005;
+/// Frames resampled before being sent to the device
+const CHUNK_FRAMES: usize = 512;
+/// Change of the master volume for each key press, in percent
+const VOLUME_STEP: u32 = 10;
+
+/// audio_output::AudioCommand
+///
+/// Change of the audio output requested with a hotkey.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum AudioCommand {
+    VolumeUp,
+    VolumeDown,
+    ToggleMute,
+}
+
+/// audio_output::AudioOutput
+///
+/// Plays the output of the sound controller on the audio device of the host. The samples are
+/// resampled from the rate selected by SOUNDBIAS to the rate of the device, and queued. The
+/// resampling ratio is adjusted by up to 0.5% depending on the amount of audio queued, so that
+/// small differences between the speed of the emulator and real time cause neither underruns nor
+/// a growing latency. When the emulator runs faster than that, it waits for the device.
+pub struct AudioOutput {
+    queue: AudioQueue<i16>,
+    resampler: Resampler,
+    buffer: Vec<i16>,
+    volume: u32, // Master volume in percent
+    muted: bool,
+}
+
+impl AudioOutput {
+    /// AudioOutput::new
+    ///
+    /// @param volume [u32]: master volume, from 0 to 100
+    /// @return [Result<AudioOutput, String>]: audio output, or the error of SDL
+    pub fn new(volume: u32) -> Result<Self, String> {
+        let audio_subsystem = sdl2::init()?.audio()?;
+        let desired = AudioSpecDesired {
+            freq: Some(OUTPUT_RATE),
+            channels: Some(2),
+            samples: Some(1024),
+        };
+        let queue = audio_subsystem.open_queue::<i16, _>(None, &desired)?;
+        if queue.spec().channels != 2 {
+            return Err(String::from("the audio device is not stereo"));
+        }
+        let output_rate = queue.spec().freq as u32;
+        queue.resume();
+
+        Ok(Self {
+            queue,
+            resampler: Resampler::new(32768, output_rate),
+            buffer: Vec::with_capacity(CHUNK_FRAMES * 2 + 16),
+            volume: volume.min(100),
+            muted: false,
+        })
+    }
+
+    /// AudioOutput::push
+    ///
+    /// @param frame [(i16, i16)]: left and right sample of the sound controller
+    /// @param input_rate [u32]: rate of the samples of the sound controller, in Hz
+    pub fn push(&mut self, frame: (i16, i16), input_rate: u32) {
+        self.resampler.set_input_rate(input_rate);
+        self.resampler.push(frame, &mut self.buffer);
+        if self.buffer.len() >= CHUNK_FRAMES * 2 {
+            self.flush();
+        }
+    }
+
+    /// AudioOutput::apply
+    ///
+    /// @param command [AudioCommand]: change requested by the user
+    pub fn apply(&mut self, command: AudioCommand) {
+        match command {
+            AudioCommand::VolumeUp => self.volume = (self.volume + VOLUME_STEP).min(100),
+            AudioCommand::VolumeDown => self.volume = self.volume.saturating_sub(VOLUME_STEP),
+            AudioCommand::ToggleMute => self.muted = !self.muted,
+        }
+
+        if self.muted {
+            println!("Volume: muted");
+        } else {
+            println!("Volume: {}%", self.volume);
+        }
+    }
+
+    /// AudioOutput::flush
+    ///
+    /// Send the resampled frames to the device, and adjust the resampling ratio.
+    fn flush(&mut self) {
+        let volume = if self.muted { 0 } else { self.volume as i32 };
+        for sample in self.buffer.iter_mut() {
+            *sample = (*sample as i32 * volume / 100) as i16;
+        }
+        let _ = self.queue.queue_audio(&self.buffer);
+        self.buffer.clear();
+
+        // Running too fast: wait for the device
+        while self.queued_frames() > 2 * TARGET_QUEUED_FRAMES {
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        let fill = self.queued_frames() as f64 / TARGET_QUEUED_FRAMES as f64;
+        self.resampler
+            .set_adjust((fill - 1.0).clamp(-1.0, 1.0) * MAX_RATE_ADJUST);
+    }
+
+    /// AudioOutput::queued_frames
+    ///
+    /// @return [u32]: frames waiting to be played by the device
+    fn queued_frames(&self) -> u32 {
+        self.queue.size() / 4
+    }
+}
@@ -0,0 +1,612 @@
+use crate::common::BitOperation;
+
+/// Waveforms of the square channels for each duty cycle (12.5%, 25%, 50%, 75%), one bit per step
+const DUTY_PATTERNS: [u8; 4] = [0b00000001, 0b10000001, 0b10000111, 0b01111110];
+
+/// channels::LengthCounter
+///
+/// Disables a channel after a programmable time, when enabled. It is clocked at 256Hz.
+pub struct LengthCounter {
+    pub enabled: bool,
+    counter: u32,
+    max: u32,
+}
+
+impl LengthCounter {
+    /// LengthCounter::new
+    ///
+    /// @param max [u32]: largest length of the channel, 64 or 256
+    pub fn new(max: u32) -> Self {
+        Self {
+            enabled: false,
+            counter: 0,
+            max,
+        }
+    }
+
+    /// LengthCounter::load
+    ///
+    /// @param length [u32]: length field of the register, the counter being `max - length`
+    pub fn load(&mut self, length: u32) {
+        self.counter = self.max - (length & (self.max - 1));
+    }
+
+    /// LengthCounter::trigger
+    ///
+    /// An expired counter is reloaded with the maximum length when the channel is restarted.
+    pub fn trigger(&mut self) {
+        if self.counter == 0 {
+            self.counter = self.max;
+        }
+    }
+
+    /// LengthCounter::tick
+    ///
+    /// @return [bool]: true if the channel is to be disabled
+    pub fn tick(&mut self) -> bool {
+        if !self.enabled || self.counter == 0 {
+            return false;
+        }
+        self.counter -= 1;
+        self.counter == 0
+    }
+}
+
+/// channels::Envelope
+///
+/// Volume of channels 1, 2 and 4, increased or decreased every `period` ticks of a 64Hz clock.
+/// It is configured by bits 8-15 of the register: period (8-10), direction (11, 1 to increase)
+/// and initial volume (12-15).
+pub struct Envelope {
+    pub volume: u32,
+    initial_volume: u32,
+    increase: bool,
+    period: u32,
+    timer: u32,
+}
+
+impl Envelope {
+    pub fn new() -> Self {
+        Self {
+            volume: 0,
+            initial_volume: 0,
+            increase: false,
+            period: 0,
+            timer: 0,
+        }
+    }
+
+    /// Envelope::configure
+    ///
+    /// @param value [u32]: register containing the envelope in bits 8-15
+    pub fn configure(&mut self, value: u32) {
+        self.initial_volume = value.get_range(15, 12);
+        self.increase = value.is_bit_set(11);
+        self.period = value.get_range(10, 8);
+    }
+
+    /// Envelope::is_dac_enabled
+    ///
+    /// @return [bool]: false if the channel is muted by an initial volume of 0 decreasing
+    pub fn is_dac_enabled(&self) -> bool {
+        self.initial_volume != 0 || self.increase
+    }
+
+    /// Envelope::trigger
+    pub fn trigger(&mut self) {
+        self.volume = self.initial_volume;
+        self.timer = self.period;
+    }
+
+    /// Envelope::tick
+    pub fn tick(&mut self) {
+        if self.period == 0 {
+            return;
+        }
+
+        self.timer -= 1;
+        if self.timer == 0 {
+            self.timer = self.period;
+            if self.increase && self.volume < 15 {
+                self.volume += 1;
+            } else if !self.increase && self.volume > 0 {
+                self.volume -= 1;
+            }
+        }
+    }
+}
+
+/// channels::SweepEvent
+///
+/// Result of a tick of the frequency sweep.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SweepEvent {
+    None,
+    Frequency(u32),
+    Overflow,
+}
+
+/// channels::Sweep
+///
+/// Frequency sweep of channel 1, configured by SOUND1CNT_L: shift (0-2), direction (3, 1 to
+/// decrease) and period in ticks of a 128Hz clock (4-6). At each period, the frequency changes by
+/// `frequency >> shift`, and the channel is disabled if it goes beyond 2047.
+pub struct Sweep {
+    shift: u32,
+    decrease: bool,
+    period: u32,
+    timer: u32,
+    enabled: bool,
+    shadow: u32,
+}
+
+impl Sweep {
+    pub fn new() -> Self {
+        Self {
+            shift: 0,
+            decrease: false,
+            period: 0,
+            timer: 0,
+            enabled: false,
+            shadow: 0,
+        }
+    }
+
+    /// Sweep::configure
+    ///
+    /// @param value [u32]: content of SOUND1CNT_L
+    pub fn configure(&mut self, value: u32) {
+        self.shift = value.get_range(2, 0);
+        self.decrease = value.is_bit_set(3);
+        self.period = value.get_range(6, 4);
+    }
+
+    /// Sweep::trigger
+    ///
+    /// @param frequency [u32]: frequency of the channel
+    /// @return [bool]: false if the channel is to be disabled because of an overflow
+    pub fn trigger(&mut self, frequency: u32) -> bool {
+        self.shadow = frequency;
+        self.timer = self.reload_value();
+        self.enabled = self.period != 0 || self.shift != 0;
+        self.shift == 0 || self.next_frequency() <= 2047
+    }
+
+    /// Sweep::tick
+    ///
+    /// @return [SweepEvent]: change of the frequency of the channel
+    pub fn tick(&mut self) -> SweepEvent {
+        self.timer -= 1;
+        if self.timer != 0 {
+            return SweepEvent::None;
+        }
+
+        self.timer = self.reload_value();
+        if !self.enabled || self.period == 0 {
+            return SweepEvent::None;
+        }
+
+        let frequency = self.next_frequency();
+        if frequency > 2047 {
+            return SweepEvent::Overflow;
+        }
+        if self.shift == 0 {
+            return SweepEvent::None;
+        }
+
+        self.shadow = frequency;
+        if self.next_frequency() > 2047 {
+            return SweepEvent::Overflow;
+        }
+        SweepEvent::Frequency(frequency)
+    }
+
+    /// Sweep::next_frequency
+    ///
+    /// @return [u32]: frequency after the next step of the sweep
+    fn next_frequency(&self) -> u32 {
+        let delta = self.shadow >> self.shift;
+        if self.decrease {
+            self.shadow - delta
+        } else {
+            self.shadow + delta
+        }
+    }
+
+    /// Sweep::reload_value
+    ///
+    /// @return [u32]: ticks until the next step, a period of 0 being treated as 8
+    fn reload_value(&self) -> u32 {
+        if self.period == 0 {
+            8
+        } else {
+            self.period
+        }
+    }
+}
+
+/// channels::SquareChannel
+///
+/// Square wave of channels 1 and 2. Each of the 8 steps of the waveform lasts
+/// `(2048 - frequency) * 16` cycles, for an output frequency of `131072 / (2048 - frequency)`Hz.
+pub struct SquareChannel {
+    pub enabled: bool,
+    pub length: LengthCounter,
+    pub envelope: Envelope,
+    pub sweep: Option<Sweep>,
+    pub frequency: u32,
+    pub duty: u32,
+    timer: u32,
+    step: u32,
+}
+
+impl SquareChannel {
+    /// SquareChannel::new
+    ///
+    /// @param has_sweep [bool]: true for channel 1
+    pub fn new(has_sweep: bool) -> Self {
+        Self {
+            enabled: false,
+            length: LengthCounter::new(64),
+            envelope: Envelope::new(),
+            sweep: has_sweep.then(Sweep::new),
+            frequency: 0,
+            duty: 0,
+            timer: 0,
+            step: 0,
+        }
+    }
+
+    /// SquareChannel::trigger
+    pub fn trigger(&mut self) {
+        self.enabled = self.envelope.is_dac_enabled();
+        self.length.trigger();
+        self.envelope.trigger();
+        self.timer = self.period();
+        if let Some(sweep) = self.sweep.as_mut() {
+            if !sweep.trigger(self.frequency) {
+                self.enabled = false;
+            }
+        }
+    }
+
+    /// SquareChannel::step
+    ///
+    /// Advance the channel by one cycle.
+    pub fn step(&mut self) {
+        if self.timer <= 1 {
+            self.timer = self.period();
+            self.step = (self.step + 1) % 8;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    /// SquareChannel::output
+    ///
+    /// @return [i32]: current amplitude, from -15 to 15
+    pub fn output(&self) -> i32 {
+        if !self.enabled {
+            return 0;
+        }
+
+        let volume = self.envelope.volume as i32;
+        if (DUTY_PATTERNS[self.duty as usize] >> self.step) & 1 == 1 {
+            volume
+        } else {
+            -volume
+        }
+    }
+
+    /// SquareChannel::period
+    ///
+    /// @return [u32]: cycles of each step of the waveform
+    fn period(&self) -> u32 {
+        (2048 - self.frequency) * 16
+    }
+}
+
+/// channels::WaveChannel
+///
+/// Channel 3, playing 4 bits samples from the wave RAM, high nibble first. The RAM contains two
+/// banks of 32 samples, which are either played one at a time or one after the other. Each sample
+/// lasts `(2048 - frequency) * 8` cycles.
+pub struct WaveChannel {
+    pub enabled: bool,
+    pub dac_enabled: bool,
+    pub length: LengthCounter,
+    pub wave_ram: [u8; 32],
+    pub frequency: u32,
+    pub two_banks: bool,
+    pub bank: usize,
+    pub volume: u32,        // 0 mute, 1 100%, 2 50%, 3 25%
+    pub force_volume: bool, // 75% regardless of `volume`
+    timer: u32,
+    position: usize,
+    sample: u8,
+}
+
+impl WaveChannel {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            dac_enabled: false,
+            length: LengthCounter::new(256),
+            wave_ram: [0; 32],
+            frequency: 0,
+            two_banks: false,
+            bank: 0,
+            volume: 0,
+            force_volume: false,
+            timer: 0,
+            position: 0,
+            sample: 0,
+        }
+    }
+
+    /// WaveChannel::trigger
+    pub fn trigger(&mut self) {
+        self.enabled = self.dac_enabled;
+        self.length.trigger();
+        self.timer = self.period();
+        self.position = 0;
+        self.sample = self.read_sample();
+    }
+
+    /// WaveChannel::step
+    ///
+    /// Advance the channel by one cycle.
+    pub fn step(&mut self) {
+        if self.timer <= 1 {
+            self.timer = self.period();
+            let samples = if self.two_banks { 64 } else { 32 };
+            self.position = (self.position + 1) % samples;
+            self.sample = self.read_sample();
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    /// WaveChannel::output
+    ///
+    /// @return [i32]: current amplitude, from -15 to 15
+    pub fn output(&self) -> i32 {
+        if !self.enabled {
+            return 0;
+        }
+
+        let amplitude = self.sample as i32 * 2 - 15;
+        if self.force_volume {
+            return amplitude * 3 / 4;
+        }
+        match self.volume {
+            1 => amplitude,
+            2 => amplitude / 2,
+            3 => amplitude / 4,
+            _ => 0,
+        }
+    }
+
+    /// WaveChannel::read_sample
+    ///
+    /// @return [u8]: sample at the current position, starting from the selected bank
+    fn read_sample(&self) -> u8 {
+        let index = (self.bank * 32 + self.position) % 64;
+        let byte = self.wave_ram[index / 2];
+        if index.is_multiple_of(2) {
+            byte >> 4
+        } else {
+            byte & 0xf
+        }
+    }
+
+    /// WaveChannel::period
+    ///
+    /// @return [u32]: cycles of each sample
+    fn period(&self) -> u32 {
+        (2048 - self.frequency) * 8
+    }
+}
+
+/// channels::NoiseChannel
+///
+/// Channel 4, outputting the lowest bit of a 15 bits (or 7 bits) linear feedback shift register.
+/// The register is shifted every `divisor << shift` cycles of the 4MHz clock of the Game Boy,
+/// where the divisor is 8 for a ratio of 0 and `16 * ratio` otherwise.
+pub struct NoiseChannel {
+    pub enabled: bool,
+    pub length: LengthCounter,
+    pub envelope: Envelope,
+    pub ratio: u32,
+    pub shift: u32,
+    pub width_7: bool,
+    timer: u32,
+    lfsr: u16,
+}
+
+impl NoiseChannel {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            length: LengthCounter::new(64),
+            envelope: Envelope::new(),
+            ratio: 0,
+            shift: 0,
+            width_7: false,
+            timer: 0,
+            lfsr: 0x7fff,
+        }
+    }
+
+    /// NoiseChannel::trigger
+    pub fn trigger(&mut self) {
+        self.enabled = self.envelope.is_dac_enabled();
+        self.length.trigger();
+        self.envelope.trigger();
+        self.timer = self.period();
+        self.lfsr = 0x7fff;
+    }
+
+    /// NoiseChannel::step
+    ///
+    /// Advance the channel by one cycle. Shifts of 14 and 15 stop the register.
+    pub fn step(&mut self) {
+        if self.shift >= 14 {
+            return;
+        }
+
+        if self.timer <= 1 {
+            self.timer = self.period();
+            let feedback = (self.lfsr ^ (self.lfsr >> 1)) & 1;
+            self.lfsr = (self.lfsr >> 1) | (feedback << 14);
+            if self.width_7 {
+                self.lfsr = (self.lfsr & !0x40) | (feedback << 6);
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    /// NoiseChannel::output
+    ///
+    /// @return [i32]: current amplitude, from -15 to 15
+    pub fn output(&self) -> i32 {
+        if !self.enabled {
+            return 0;
+        }
+
+        let volume = self.envelope.volume as i32;
+        if self.lfsr & 1 == 0 {
+            volume
+        } else {
+            -volume
+        }
+    }
+
+    /// NoiseChannel::period
+    ///
+    /// @return [u32]: cycles between two shifts of the register, the gba clock being 4 times
+    /// faster than the one of the Game Boy
+    fn period(&self) -> u32 {
+        let divisor = if self.ratio == 0 { 8 } else { 16 * self.ratio };
+        (divisor << self.shift) * 4
+    }
+}
+
+#[cfg(test)]
+mod test_channels {
+
+    use crate::apu::channels::{
+        Envelope, LengthCounter, NoiseChannel, SquareChannel, Sweep, SweepEvent, WaveChannel,
+    };
+
+    #[test]
+    fn test_length_and_envelope() {
+        let mut length = LengthCounter::new(64);
+        length.load(62);
+        assert!(!length.tick());
+        length.enabled = true;
+        assert!(!length.tick());
+        assert!(length.tick());
+        length.trigger();
+        (0..63).for_each(|_| assert!(!length.tick()));
+        assert!(length.tick());
+
+        // Initial volume 2, decreasing every 2 ticks
+        let mut envelope = Envelope::new();
+        envelope.configure(0x2200);
+        envelope.trigger();
+        let volumes: Vec<u32> = (0..6)
+            .map(|_| {
+                envelope.tick();
+                envelope.volume
+            })
+            .collect();
+        assert_eq!(volumes, vec![2, 1, 1, 0, 0, 0]);
+        envelope.configure(0x0000);
+        assert!(!envelope.is_dac_enabled());
+    }
+
+    #[test]
+    fn test_sweep() {
+        // Period 1, increasing by frequency >> 1
+        let mut sweep = Sweep::new();
+        sweep.configure(0x11);
+        assert!(sweep.trigger(0x200));
+        assert_eq!(sweep.tick(), SweepEvent::Frequency(0x300));
+        assert_eq!(sweep.tick(), SweepEvent::Frequency(0x480));
+
+        // The next frequency is checked again after an update
+        assert_eq!(sweep.tick(), SweepEvent::Overflow);
+
+        // Decreasing sweeps never overflow
+        sweep.configure(0x19);
+        assert!(sweep.trigger(0x7ff));
+        assert_eq!(sweep.tick(), SweepEvent::Frequency(0x400));
+    }
+
+    #[test]
+    fn test_square_channel() {
+        let mut channel = SquareChannel::new(false);
+        channel.frequency = 2047;
+        channel.duty = 2;
+        channel.envelope.configure(0xf000);
+        channel.trigger();
+
+        // 50% duty cycle, 16 cycles per step
+        let waveform: Vec<i32> = (0..8)
+            .map(|_| {
+                (0..16).for_each(|_| channel.step());
+                channel.output()
+            })
+            .collect();
+        assert_eq!(waveform, vec![15, 15, -15, -15, -15, -15, 15, 15]);
+
+        channel.envelope.configure(0x0000);
+        channel.trigger();
+        assert!(!channel.enabled);
+        assert_eq!(channel.output(), 0);
+    }
+
+    #[test]
+    fn test_wave_channel() {
+        let mut channel = WaveChannel::new();
+        channel.wave_ram[0] = 0xf0;
+        channel.wave_ram[16] = 0x8f;
+        channel.frequency = 2047;
+        channel.dac_enabled = true;
+        channel.volume = 1;
+        channel.trigger();
+        assert_eq!(channel.output(), 15);
+        (0..8).for_each(|_| channel.step());
+        assert_eq!(channel.output(), -15);
+
+        // The second bank is played first when selected
+        channel.bank = 1;
+        channel.volume = 2;
+        channel.trigger();
+        assert_eq!(channel.output(), 0);
+        channel.force_volume = true;
+        (0..8).for_each(|_| channel.step());
+        assert_eq!(channel.output(), 11);
+    }
+
+    #[test]
+    fn test_noise_channel() {
+        let mut channel = NoiseChannel::new();
+        channel.width_7 = true;
+        channel.envelope.configure(0xf000);
+        channel.trigger();
+
+        // The 7 bits register repeats every 127 shifts, with 32 cycles between shifts
+        let sequence: Vec<i32> = (0..254)
+            .map(|_| {
+                (0..32).for_each(|_| channel.step());
+                channel.output()
+            })
+            .collect();
+        assert_eq!(sequence[..127], sequence[127..]);
+        assert!(sequence[..127].iter().any(|output| *output > 0));
+        assert!(sequence[..127].iter().any(|output| *output < 0));
+    }
+}
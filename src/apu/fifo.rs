@@ -0,0 +1,84 @@
+use std::collections::VecDeque;
+
+/// Capacity of a FIFO, in samples
+const FIFO_SIZE: usize = 32;
+/// Number of samples below which a FIFO requests a refill
+const REFILL_THRESHOLD: usize = 16;
+
+/// fifo::Fifo
+///
+/// Queue of signed 8 bits samples of a Direct Sound channel. The cpu or a DMA channel fills it
+/// through FIFO_A or FIFO_B, and the channel plays the next sample at each overflow of its timer.
+/// Samples written to a full FIFO are lost.
+pub struct Fifo {
+    samples: VecDeque<i8>,
+    pub current: i8,
+}
+
+impl Fifo {
+    pub fn new() -> Self {
+        Self {
+            samples: VecDeque::with_capacity(FIFO_SIZE),
+            current: 0,
+        }
+    }
+
+    /// Fifo::write
+    ///
+    /// @param data [u32]: halfword written to the FIFO
+    /// @param lanes [u32]: bits of the halfword involved in the transfer
+    pub fn write(&mut self, data: u32, lanes: u32) {
+        for byte in 0..2 {
+            if (lanes >> (byte * 8)) & 0xff != 0 && self.samples.len() < FIFO_SIZE {
+                self.samples.push_back((data >> (byte * 8)) as u8 as i8);
+            }
+        }
+    }
+
+    /// Fifo::reset
+    pub fn reset(&mut self) {
+        self.samples.clear();
+        self.current = 0;
+    }
+
+    /// Fifo::timer_overflow
+    ///
+    /// Play the next sample. The last sample keeps playing when the FIFO is empty.
+    ///
+    /// @return [bool]: true if the FIFO needs to be refilled
+    pub fn timer_overflow(&mut self) -> bool {
+        if let Some(sample) = self.samples.pop_front() {
+            self.current = sample;
+        }
+        self.samples.len() <= REFILL_THRESHOLD
+    }
+}
+
+#[cfg(test)]
+mod test_fifo {
+
+    use crate::apu::fifo::Fifo;
+
+    #[test]
+    fn test_fifo() {
+        let mut fifo = Fifo::new();
+        (0..20).for_each(|_| fifo.write(0x80ff, 0xffff));
+        fifo.write(0x0101, 0x00ff);
+
+        assert!(!fifo.timer_overflow());
+        assert_eq!(fifo.current, -1);
+        assert!(!fifo.timer_overflow());
+        assert_eq!(fifo.current, -128);
+
+        // Only 32 samples are kept
+        (0..14).for_each(|_| {
+            fifo.timer_overflow();
+        });
+        assert!(fifo.timer_overflow());
+        assert_eq!(fifo.current, -1);
+
+        fifo.reset();
+        assert!(fifo.timer_overflow());
+        assert_eq!(fifo.current, 0);
+    }
+}
@@ -0,0 +1,582 @@
+pub mod audio_capture;
+pub mod audio_output;
+pub mod channels;
+pub mod fifo;
+pub mod resampler;
+pub mod wav_writer;
+
+use crate::apu::channels::{NoiseChannel, SquareChannel, SweepEvent, WaveChannel};
+use crate::apu::fifo::Fifo;
+use crate::common::BitOperation;
+use crate::io::registers::IoRegisters;
+
+pub const SOUND1CNT_L: u32 = 0x04000060;
+pub const SOUND1CNT_H: u32 = 0x04000062;
+pub const SOUND1CNT_X: u32 = 0x04000064;
+pub const SOUND2CNT_L: u32 = 0x04000068;
+pub const SOUND2CNT_H: u32 = 0x0400006c;
+pub const SOUND3CNT_L: u32 = 0x04000070;
+pub const SOUND3CNT_H: u32 = 0x04000072;
+pub const SOUND3CNT_X: u32 = 0x04000074;
+pub const SOUND4CNT_L: u32 = 0x04000078;
+pub const SOUND4CNT_H: u32 = 0x0400007c;
+pub const SOUNDCNT_L: u32 = 0x04000080;
+pub const SOUNDCNT_H: u32 = 0x04000082;
+pub const SOUNDCNT_X: u32 = 0x04000084;
+pub const SOUNDBIAS: u32 = 0x04000088;
+pub const WAVE_RAM: u32 = 0x04000090;
+pub const FIFO_A: u32 = 0x040000a0;
+pub const FIFO_B: u32 = 0x040000a4;
+
+/// Cycles between two steps of the frame sequencer, which runs at 512Hz
+const FRAME_SEQUENCER_PERIOD: u32 = 32768;
+/// Frequency of the system clock, in Hz
+pub const CLOCK_RATE: u32 = 16777216;
+
+/// apu::Apu
+///
+/// Sound controller. This handles the four channels inherited from the Game Boy (PSG): two square
+/// waves, the first one with a frequency sweep, a channel playing samples from the wave RAM and a
+/// noise generator. The frame sequencer clocks the modulation units of the channels:
+///
+/// Step    Length (256Hz)    Sweep (128Hz)    Envelope (64Hz)
+/// -------------------------------------------------------
+/// 0       x
+/// 2       x                 x
+/// 4       x
+/// 6       x                 x
+/// 7                                          x
+/// -------------------------------------------------------
+///
+/// The two Direct Sound channels play 8 bits samples from FIFO A and FIFO B, which are usually
+/// refilled by DMA channels 1 and 2. The mixer produces samples at a rate selected by bits 14-15
+/// of SOUNDBIAS: 32768Hz, 65536Hz, 131072Hz or 262144Hz.
+pub struct Apu {
+    pub square1: SquareChannel,
+    pub square2: SquareChannel,
+    pub wave: WaveChannel,
+    pub noise: NoiseChannel,
+    pub fifos: [Fifo; 2],
+    sequencer_timer: u32,
+    sequencer_step: u32,
+    sample_timer: u32,
+}
+
+impl Apu {
+    pub fn new() -> Self {
+        Self {
+            square1: SquareChannel::new(true),
+            square2: SquareChannel::new(false),
+            wave: WaveChannel::new(),
+            noise: NoiseChannel::new(),
+            fifos: [Fifo::new(), Fifo::new()],
+            sequencer_timer: FRAME_SEQUENCER_PERIOD,
+            sequencer_step: 0,
+            sample_timer: 0,
+        }
+    }
+
+    /// Apu::is_enabled
+    ///
+    /// @param io [&IoRegisters]: I/O registers
+    /// @return [bool]: true if the master enable of SOUNDCNT_X is set
+    pub fn is_enabled(io: &IoRegisters) -> bool {
+        io.get(SOUNDCNT_X).is_bit_set(7)
+    }
+
+    /// Apu::sample_rate
+    ///
+    /// @param io [&IoRegisters]: I/O registers
+    /// @return [u32]: rate of the samples of the mixer, in Hz
+    pub fn sample_rate(io: &IoRegisters) -> u32 {
+        CLOCK_RATE / Self::sample_period(io)
+    }
+
+    /// Apu::sample_period
+    ///
+    /// @param io [&IoRegisters]: I/O registers
+    /// @return [u32]: cycles between two samples of the mixer
+    fn sample_period(io: &IoRegisters) -> u32 {
+        512 >> io.get(SOUNDBIAS).get_range(15, 14)
+    }
+
+    /// Apu::step
+    ///
+    /// Advance the sound controller by one cycle.
+    ///
+    /// @param io [&mut IoRegisters]: I/O registers
+    /// @return [Option<(i16, i16)>]: left and right output, at the rate of the mixer
+    pub fn step(&mut self, io: &mut IoRegisters) -> Option<(i16, i16)> {
+        if Self::is_enabled(io) {
+            self.step_channels(io);
+        }
+
+        self.sample_timer += 1;
+        if self.sample_timer < Self::sample_period(io) {
+            return None;
+        }
+        self.sample_timer = 0;
+        Some(self.output(io))
+    }
+
+    /// Apu::step_channels
+    ///
+    /// Advance the PSG channels by one cycle, and update their status in SOUNDCNT_X.
+    ///
+    /// @param io [&mut IoRegisters]: I/O registers
+    fn step_channels(&mut self, io: &mut IoRegisters) {
+        self.square1.step();
+        self.square2.step();
+        self.wave.step();
+        self.noise.step();
+
+        self.sequencer_timer -= 1;
+        if self.sequencer_timer == 0 {
+            self.sequencer_timer = FRAME_SEQUENCER_PERIOD;
+            self.step_sequencer(io);
+            self.sequencer_step = (self.sequencer_step + 1) % 8;
+        }
+
+        let status = (self.square1.enabled as u32)
+            | ((self.square2.enabled as u32) << 1)
+            | ((self.wave.enabled as u32) << 2)
+            | ((self.noise.enabled as u32) << 3);
+        let soundcnt_x = io.get(SOUNDCNT_X);
+        if soundcnt_x & 0xf != status {
+            io.set(SOUNDCNT_X, (soundcnt_x & !0xf) | status);
+        }
+    }
+
+    /// Apu::step_sequencer
+    ///
+    /// @param io [&mut IoRegisters]: I/O registers, to report the frequency changes of the sweep
+    fn step_sequencer(&mut self, io: &mut IoRegisters) {
+        if self.sequencer_step.is_multiple_of(2) {
+            if self.square1.length.tick() {
+                self.square1.enabled = false;
+            }
+            if self.square2.length.tick() {
+                self.square2.enabled = false;
+            }
+            if self.wave.length.tick() {
+                self.wave.enabled = false;
+            }
+            if self.noise.length.tick() {
+                self.noise.enabled = false;
+            }
+        }
+
+        if self.sequencer_step == 2 || self.sequencer_step == 6 {
+            if let Some(sweep) = self.square1.sweep.as_mut() {
+                match sweep.tick() {
+                    SweepEvent::None => {}
+                    SweepEvent::Frequency(frequency) => {
+                        self.square1.frequency = frequency;
+                        io.set(SOUND1CNT_X, (io.get(SOUND1CNT_X) & !0x7ff) | frequency);
+                    }
+                    SweepEvent::Overflow => self.square1.enabled = false,
+                }
+            }
+        }
+
+        if self.sequencer_step == 7 {
+            self.square1.envelope.tick();
+            self.square2.envelope.tick();
+            self.noise.envelope.tick();
+        }
+    }
+
+    /// Apu::write_register
+    ///
+    /// Apply a write of the cpu to a sound register.
+    ///
+    /// @param io [&mut IoRegisters]: I/O registers, already updated with the write
+    /// @param address [u32]: address of the halfword written
+    /// @param lanes [u32]: bits of the halfword involved in the transfer
+    pub fn write_register(&mut self, io: &mut IoRegisters, address: u32, lanes: u32) {
+        // The PSG registers can't be written while the sound is disabled
+        if address < SOUNDCNT_H && !Self::is_enabled(io) {
+            io.set(address, 0);
+            return;
+        }
+
+        let value = io.get(address);
+        match address {
+            SOUND1CNT_L => self.square1.sweep.as_mut().unwrap().configure(value),
+            SOUND1CNT_H => Self::write_square_control(&mut self.square1, value, lanes),
+            SOUND1CNT_X => {
+                Self::write_square_frequency(&mut self.square1, value);
+                Self::clear_trigger(io, address);
+            }
+            SOUND2CNT_L => Self::write_square_control(&mut self.square2, value, lanes),
+            SOUND2CNT_H => {
+                Self::write_square_frequency(&mut self.square2, value);
+                Self::clear_trigger(io, address);
+            }
+            SOUND3CNT_L => {
+                let bank = value.get_range(6, 6) as usize;
+                if bank != self.wave.bank {
+                    self.wave.bank = bank;
+                    self.load_wave_ram(io);
+                }
+                self.wave.two_banks = value.is_bit_set(5);
+                self.wave.dac_enabled = value.is_bit_set(7);
+                if !self.wave.dac_enabled {
+                    self.wave.enabled = false;
+                }
+            }
+            SOUND3CNT_H => {
+                if lanes & 0xff != 0 {
+                    self.wave.length.load(value & 0xff);
+                }
+                self.wave.volume = value.get_range(14, 13);
+                self.wave.force_volume = value.is_bit_set(15);
+            }
+            SOUND3CNT_X => {
+                self.wave.frequency = value & 0x7ff;
+                self.wave.length.enabled = value.is_bit_set(14);
+                if value.is_bit_set(15) {
+                    self.wave.trigger();
+                }
+                Self::clear_trigger(io, address);
+            }
+            SOUND4CNT_L => {
+                if lanes & 0xff != 0 {
+                    self.noise.length.load(value & 0x3f);
+                }
+                self.noise.envelope.configure(value);
+                if !self.noise.envelope.is_dac_enabled() {
+                    self.noise.enabled = false;
+                }
+            }
+            SOUND4CNT_H => {
+                self.noise.ratio = value.get_range(2, 0);
+                self.noise.width_7 = value.is_bit_set(3);
+                self.noise.shift = value.get_range(7, 4);
+                self.noise.length.enabled = value.is_bit_set(14);
+                if value.is_bit_set(15) {
+                    self.noise.trigger();
+                }
+                Self::clear_trigger(io, address);
+            }
+            SOUNDCNT_H => {
+                for (fifo, reset_bit) in [(0, 11), (1, 15)] {
+                    if value.is_bit_set(reset_bit) {
+                        self.fifos[fifo].reset();
+                    }
+                }
+                io.set(address, value & !0x8800);
+            }
+            SOUNDCNT_X if !value.is_bit_set(7) => self.power_off(io),
+            _ if (FIFO_A..FIFO_B + 4).contains(&address) => {
+                self.fifos[((address - FIFO_A) / 4) as usize].write(value, lanes);
+            }
+            _ if (WAVE_RAM..WAVE_RAM + 16).contains(&address) => {
+                let index = self.cpu_wave_bank() * 16 + (address - WAVE_RAM) as usize;
+                self.wave.wave_ram[index] = value as u8;
+                self.wave.wave_ram[index + 1] = (value >> 8) as u8;
+            }
+            _ => {}
+        }
+    }
+
+    /// Apu::write_square_control
+    ///
+    /// @param channel [&mut SquareChannel]: channel 1 or 2
+    /// @param value [u32]: content of SOUND1CNT_H or SOUND2CNT_L
+    /// @param lanes [u32]: bits of the register involved in the transfer
+    fn write_square_control(channel: &mut SquareChannel, value: u32, lanes: u32) {
+        if lanes & 0xff != 0 {
+            channel.length.load(value & 0x3f);
+        }
+        channel.duty = value.get_range(7, 6);
+        channel.envelope.configure(value);
+        if !channel.envelope.is_dac_enabled() {
+            channel.enabled = false;
+        }
+    }
+
+    /// Apu::write_square_frequency
+    ///
+    /// @param channel [&mut SquareChannel]: channel 1 or 2
+    /// @param value [u32]: content of SOUND1CNT_X or SOUND2CNT_H
+    fn write_square_frequency(channel: &mut SquareChannel, value: u32) {
+        channel.frequency = value & 0x7ff;
+        channel.length.enabled = value.is_bit_set(14);
+        if value.is_bit_set(15) {
+            channel.trigger();
+        }
+    }
+
+    /// Apu::clear_trigger
+    ///
+    /// The restart bit is write-only: clear it, so that a later write to the other byte of the
+    /// register does not restart the channel again.
+    ///
+    /// @param io [&mut IoRegisters]: I/O registers
+    /// @param address [u32]: address of the register
+    fn clear_trigger(io: &mut IoRegisters, address: u32) {
+        io.set(address, io.get(address).clear_bit(15));
+    }
+
+    /// Apu::cpu_wave_bank
+    ///
+    /// @return [usize]: bank of the wave RAM mapped at WAVE_RAM, the one which is not played
+    fn cpu_wave_bank(&self) -> usize {
+        1 - self.wave.bank
+    }
+
+    /// Apu::load_wave_ram
+    ///
+    /// Map the bank which is not played to the I/O registers, after a change of bank.
+    ///
+    /// @param io [&mut IoRegisters]: I/O registers
+    fn load_wave_ram(&self, io: &mut IoRegisters) {
+        let bank = &self.wave.wave_ram[self.cpu_wave_bank() * 16..][..16];
+        for (offset, bytes) in bank.chunks(2).enumerate() {
+            let value = bytes[0] as u32 | ((bytes[1] as u32) << 8);
+            io.set(WAVE_RAM + offset as u32 * 2, value);
+        }
+    }
+
+    /// Apu::power_off
+    ///
+    /// Disabling the sound stops the channels and clears the PSG registers. The wave RAM is kept.
+    ///
+    /// @param io [&mut IoRegisters]: I/O registers
+    fn power_off(&mut self, io: &mut IoRegisters) {
+        let wave_ram = self.wave.wave_ram;
+        self.square1 = SquareChannel::new(true);
+        self.square2 = SquareChannel::new(false);
+        self.wave = WaveChannel::new();
+        self.noise = NoiseChannel::new();
+        self.sequencer_timer = FRAME_SEQUENCER_PERIOD;
+        self.sequencer_step = 0;
+        self.wave.wave_ram = wave_ram;
+        self.load_wave_ram(io);
+        for address in (SOUND1CNT_L..SOUNDCNT_H).step_by(2) {
+            io.set(address, 0);
+        }
+        io.set(SOUNDCNT_X, 0);
+    }
+
+    /// Apu::timer_overflow
+    ///
+    /// Play the next sample of the FIFOs driven by the timers which overflowed, selected by bits
+    /// 10 (FIFO A) and 14 (FIFO B) of SOUNDCNT_H.
+    ///
+    /// @param io [&IoRegisters]: I/O registers
+    /// @param overflows [u32]: bit i set if timer i overflowed
+    /// @return [[bool; 2]]: true for each FIFO which needs to be refilled by DMA
+    pub fn timer_overflow(&mut self, io: &IoRegisters, overflows: u32) -> [bool; 2] {
+        let mut requests = [false; 2];
+        if !Self::is_enabled(io) {
+            return requests;
+        }
+
+        let control = io.get(SOUNDCNT_H);
+        for (fifo, request) in requests.iter_mut().enumerate() {
+            let timer = control.get_range(10 + 4 * fifo as u32, 10 + 4 * fifo as u32);
+            if overflows.is_bit_set(timer) {
+                *request = self.fifos[fifo].timer_overflow();
+            }
+        }
+        requests
+    }
+
+    /// Apu::channel_outputs
+    ///
+    /// @return [[i32; 4]]: current amplitude of each PSG channel, from -15 to 15
+    pub fn channel_outputs(&self) -> [i32; 4] {
+        [
+            self.square1.output(),
+            self.square2.output(),
+            self.wave.output(),
+            self.noise.output(),
+        ]
+    }
+
+    /// Apu::channel_samples
+    ///
+    /// @return [[i16; 6]]: output of each channel before mixing, the 4 PSG channels followed by
+    /// FIFO A and FIFO B, scaled to the range of 16 bits samples
+    pub fn channel_samples(&self) -> [i16; 6] {
+        let [square1, square2, wave, noise] = self.channel_outputs().map(|output| output * 2048);
+        [
+            square1 as i16,
+            square2 as i16,
+            wave as i16,
+            noise as i16,
+            self.fifos[0].current as i16 * 256,
+            self.fifos[1].current as i16 * 256,
+        ]
+    }
+
+    /// Apu::psg_output
+    ///
+    /// Mix the PSG channels according to SOUNDCNT_L (routing and master volume of each side) and
+    /// to the PSG ratio of SOUNDCNT_H (25%, 50% or 100%).
+    ///
+    /// @param io [&IoRegisters]: I/O registers
+    /// @return [(i32, i32)]: left and right samples, from -480 to 480
+    pub fn psg_output(&self, io: &IoRegisters) -> (i32, i32) {
+        if !Self::is_enabled(io) {
+            return (0, 0);
+        }
+
+        let control = io.get(SOUNDCNT_L);
+        let outputs = self.channel_outputs();
+        let side = |volume: u32, enable_bit: u32| {
+            let sum: i32 = (0..4)
+                .filter(|channel| control.is_bit_set(enable_bit + channel))
+                .map(|channel| outputs[channel as usize])
+                .sum();
+            sum * (volume as i32 + 1)
+        };
+
+        let shift = match io.get(SOUNDCNT_H) & 3 {
+            0 => 2,
+            1 => 1,
+            _ => 0,
+        };
+        let left = side(control.get_range(6, 4), 12) >> shift;
+        let right = side(control.get_range(2, 0), 8) >> shift;
+        (left, right)
+    }
+
+    /// Apu::output
+    ///
+    /// Mix the PSG and the Direct Sound channels, routed and scaled by SOUNDCNT_H: each FIFO plays
+    /// at 50% (sample x 2) or 100% (sample x 4). As on the hardware, the bias level of SOUNDBIAS is
+    /// added and the result is clipped to 10 bits, before being centered again for the host.
+    ///
+    /// @param io [&IoRegisters]: I/O registers
+    /// @return [(i16, i16)]: left and right samples
+    pub fn output(&self, io: &IoRegisters) -> (i16, i16) {
+        if !Self::is_enabled(io) {
+            return (0, 0);
+        }
+
+        let (mut left, mut right) = self.psg_output(io);
+        let control = io.get(SOUNDCNT_H);
+        for (fifo, channel) in self.fifos.iter().enumerate() {
+            let fifo = fifo as u32;
+            let volume = if control.is_bit_set(2 + fifo) { 4 } else { 2 };
+            let sample = channel.current as i32 * volume;
+            if control.is_bit_set(8 + 4 * fifo) {
+                right += sample;
+            }
+            if control.is_bit_set(9 + 4 * fifo) {
+                left += sample;
+            }
+        }
+
+        let bias = (io.get(SOUNDBIAS) & 0x3fe) as i32;
+        let convert = |sample: i32| (((sample + bias).clamp(0, 0x3ff) - 0x200) * 64) as i16;
+        (convert(left), convert(right))
+    }
+}
+
+#[cfg(test)]
+mod test_apu {
+
+    use crate::apu::{
+        Apu, FIFO_A, SOUND2CNT_H, SOUND2CNT_L, SOUND3CNT_L, SOUNDBIAS, SOUNDCNT_H, SOUNDCNT_L,
+        SOUNDCNT_X, WAVE_RAM,
+    };
+    use crate::bus::TransferSize;
+    use crate::io::registers::{IoHook, IoRegisters};
+
+    fn write(apu: &mut Apu, io: &mut IoRegisters, address: u32, value: u32) {
+        let data = value | (value << 16);
+        for event in io
+            .write(address, data, TransferSize::HALFWORD)
+            .into_iter()
+            .flatten()
+        {
+            assert_eq!(event.hook, IoHook::Sound);
+            apu.write_register(io, event.address, event.lanes);
+        }
+    }
+
+    #[test]
+    fn test_direct_sound() {
+        let mut apu = Apu::new();
+        let mut io = IoRegisters::new();
+        write(&mut apu, &mut io, SOUNDCNT_X, 0x0080);
+
+        // FIFO A at 100% on both sides, driven by timer 0
+        write(&mut apu, &mut io, SOUNDCNT_H, 0x0306);
+        write(&mut apu, &mut io, FIFO_A, 0x0040);
+        assert_eq!(apu.timer_overflow(&io, 0b10), [false, false]);
+        assert_eq!(apu.output(&io), (0, 0));
+        assert_eq!(apu.timer_overflow(&io, 0b01), [true, true]);
+        assert_eq!(apu.output(&io), (64 * 4 * 64, 64 * 4 * 64));
+
+        // Clipped to 10 bits after adding the bias
+        write(&mut apu, &mut io, SOUNDBIAS, 0x0300);
+        assert_eq!(apu.output(&io), (0x1ff * 64, 0x1ff * 64));
+
+        // FIFO reset, the bit being write-only
+        write(&mut apu, &mut io, SOUNDCNT_H, 0x0b06);
+        assert_eq!(io.get(SOUNDCNT_H), 0x0306);
+        assert!(apu.timer_overflow(&io, 0b01)[0]);
+        assert_eq!(apu.fifos[0].current, 0);
+    }
+
+    #[test]
+    fn test_trigger_and_mix() {
+        let mut apu = Apu::new();
+        let mut io = IoRegisters::new();
+
+        // Ignored while the sound is disabled
+        write(&mut apu, &mut io, SOUND2CNT_L, 0xf080);
+        assert_eq!(io.get(SOUND2CNT_L), 0);
+
+        write(&mut apu, &mut io, SOUNDCNT_X, 0x0080);
+        write(&mut apu, &mut io, SOUNDCNT_L, 0x2077);
+        write(&mut apu, &mut io, SOUND2CNT_L, 0xf080);
+        write(&mut apu, &mut io, SOUND2CNT_H, 0x87ff);
+        apu.step(&mut io);
+        assert_eq!(io.read(SOUNDCNT_X, 0) & 0xffff, 0x0082);
+        assert_eq!(io.get(SOUND2CNT_H) & 0x8000, 0);
+
+        // Channel 2 is only routed to the left side, at 8 times its volume, with a PSG ratio of 25%
+        let (left, right) = apu.psg_output(&io);
+        assert_eq!(left.abs(), 15 * 8 / 4);
+        assert_eq!(right, 0);
+
+        write(&mut apu, &mut io, SOUNDCNT_X, 0x0000);
+        assert_eq!(io.get(SOUND2CNT_L), 0);
+        assert_eq!(apu.psg_output(&io), (0, 0));
+    }
+
+    #[test]
+    fn test_direct_sound_while_disabled() {
+        let mut apu = Apu::new();
+        let mut io = IoRegisters::new();
+
+        // Only the PSG registers are locked, the FIFOs can still be set up
+        write(&mut apu, &mut io, SOUNDCNT_H, 0x0306);
+        assert_eq!(io.get(SOUNDCNT_H), 0x0306);
+        write(&mut apu, &mut io, SOUNDCNT_H, 0x0b06);
+        assert_eq!(io.get(SOUNDCNT_H), 0x0306);
+    }
+
+    #[test]
+    fn test_wave_ram_banks() {
+        let mut apu = Apu::new();
+        let mut io = IoRegisters::new();
+        write(&mut apu, &mut io, SOUNDCNT_X, 0x0080);
+
+        // The cpu accesses the bank which is not played
+        write(&mut apu, &mut io, WAVE_RAM, 0x3412);
+        assert_eq!(apu.wave.wave_ram[16..18], [0x12, 0x34]);
+        write(&mut apu, &mut io, SOUND3CNT_L, 0x0040);
+        assert_eq!(io.get(WAVE_RAM), 0x0000);
+        write(&mut apu, &mut io, WAVE_RAM, 0x7856);
+        assert_eq!(apu.wave.wave_ram[0..2], [0x56, 0x78]);
+        write(&mut apu, &mut io, SOUND3CNT_L, 0x0000);
+        assert_eq!(io.get(WAVE_RAM), 0x3412);
+    }
+}
@@ -0,0 +1,178 @@
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+
+/// Zero crossings of the sinc on each side of the kernel, setting the steepness of the filter
+const ZERO_CROSSINGS: f64 = 8.0;
+/// Entries of the kernel table per input sample, interpolated linearly
+const TABLE_RESOLUTION: usize = 256;
+/// Cutoff frequency, relative to the lowest of the two Nyquist frequencies
+const CUTOFF: f64 = 0.9;
+
+/// resampler::Resampler
+///
+/// Converts a stream of stereo samples between two rates using a windowed sinc (Blackman window).
+/// The cutoff follows the lowest of the two rates, so that the high frequencies of the 262kHz
+/// output of the gba do not alias when played at the host rate. The ratio can be adjusted
+/// slightly while running, to follow the speed of the emulator.
+pub struct Resampler {
+    input_rate: u32,
+    output_rate: u32,
+    adjust: f64,
+    history: VecDeque<[f32; 2]>,
+    position: f64, // Position of the next output sample, in input samples from history[0]
+    half_width: usize,
+    kernel: Vec<f32>,
+}
+
+impl Resampler {
+    /// Resampler::new
+    ///
+    /// @param input_rate [u32]: rate of the samples pushed, in Hz
+    /// @param output_rate [u32]: rate of the samples produced, in Hz
+    pub fn new(input_rate: u32, output_rate: u32) -> Self {
+        let mut resampler = Self {
+            input_rate: 0,
+            output_rate,
+            adjust: 0.0,
+            history: VecDeque::new(),
+            position: 0.0,
+            half_width: 0,
+            kernel: Vec::new(),
+        };
+        resampler.set_input_rate(input_rate);
+        resampler
+    }
+
+    /// Resampler::set_input_rate
+    ///
+    /// @param input_rate [u32]: rate of the samples pushed, in Hz
+    pub fn set_input_rate(&mut self, input_rate: u32) {
+        if input_rate == self.input_rate {
+            return;
+        }
+        self.input_rate = input_rate;
+
+        // Cutoff and width of the kernel in input samples
+        let cutoff = CUTOFF * (self.output_rate as f64 / input_rate as f64).min(1.0);
+        let half_width = ZERO_CROSSINGS / cutoff;
+        self.half_width = half_width.ceil() as usize;
+
+        let entries = self.half_width * TABLE_RESOLUTION + 1;
+        self.kernel = (0..entries)
+            .map(|index| {
+                let x = index as f64 / TABLE_RESOLUTION as f64;
+                if x >= half_width {
+                    return 0.0;
+                }
+                let sinc = if x == 0.0 {
+                    1.0
+                } else {
+                    (PI * cutoff * x).sin() / (PI * cutoff * x)
+                };
+                let phase = PI * (x / half_width + 1.0);
+                let window = 0.42 - 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos();
+                (cutoff * sinc * window) as f32
+            })
+            .collect();
+    }
+
+    /// Resampler::set_adjust
+    ///
+    /// @param adjust [f64]: relative change of the ratio, positive to produce fewer samples
+    pub fn set_adjust(&mut self, adjust: f64) {
+        self.adjust = adjust;
+    }
+
+    /// Resampler::push
+    ///
+    /// @param frame [(i16, i16)]: left and right input sample
+    /// @param output [&mut Vec<i16>]: interleaved output samples, appended as they are available
+    pub fn push(&mut self, frame: (i16, i16), output: &mut Vec<i16>) {
+        self.history.push_back([frame.0 as f32, frame.1 as f32]);
+
+        let step = self.input_rate as f64 / self.output_rate as f64 * (1.0 + self.adjust);
+        while self.position as usize + self.half_width < self.history.len() {
+            let [left, right] = self.interpolate();
+            output.push(left.round().clamp(i16::MIN as f32, i16::MAX as f32) as i16);
+            output.push(right.round().clamp(i16::MIN as f32, i16::MAX as f32) as i16);
+            self.position += step;
+        }
+
+        // Drop the samples which are out of the kernel of the next outputs
+        while self.position >= self.half_width as f64 {
+            self.history.pop_front();
+            self.position -= 1.0;
+        }
+    }
+
+    /// Resampler::interpolate
+    ///
+    /// @return [[f32; 2]]: output sample at the current position
+    fn interpolate(&self) -> [f32; 2] {
+        let center = self.position as usize;
+        let first = (center + 1).saturating_sub(self.half_width);
+        let last = (center + self.half_width).min(self.history.len() - 1);
+
+        (first..=last).fold([0.0, 0.0], |[left, right], index| {
+            let weight = self.kernel_at((self.position - index as f64).abs());
+            let [sample_left, sample_right] = self.history[index];
+            [left + sample_left * weight, right + sample_right * weight]
+        })
+    }
+
+    /// Resampler::kernel_at
+    ///
+    /// @param x [f64]: distance to the center of the kernel, in input samples
+    /// @return [f32]: weight of the input sample
+    fn kernel_at(&self, x: f64) -> f32 {
+        let position = x * TABLE_RESOLUTION as f64;
+        let index = position as usize;
+        if index + 1 >= self.kernel.len() {
+            return 0.0;
+        }
+        let fraction = (position - index as f64) as f32;
+        self.kernel[index] * (1.0 - fraction) + self.kernel[index + 1] * fraction
+    }
+}
+
+#[cfg(test)]
+mod test_resampler {
+
+    use crate::apu::resampler::Resampler;
+
+    #[test]
+    fn test_rate_and_gain() {
+        for input_rate in [32768, 262144] {
+            let mut resampler = Resampler::new(input_rate, 48000);
+            let mut output = Vec::new();
+            (0..input_rate).for_each(|_| resampler.push((1000, -1000), &mut output));
+
+            // One second of input gives one second of output, with a gain of 1
+            assert!((output.len() as i32 / 2 - 48000).abs() < 50);
+            assert!(output[1000..]
+                .chunks(2)
+                .all(|frame| (frame[0] - 1000).abs() <= 2 && (frame[1] + 1000).abs() <= 2));
+        }
+
+        // The ratio follows the adjustment
+        let mut resampler = Resampler::new(48000, 48000);
+        resampler.set_adjust(0.01);
+        let mut output = Vec::new();
+        (0..48000).for_each(|_| resampler.push((0, 0), &mut output));
+        assert!((output.len() as i32 / 2 - 47525).abs() < 50);
+    }
+
+    #[test]
+    fn test_anti_aliasing() {
+        // A tone above the output Nyquist frequency is filtered out
+        let mut resampler = Resampler::new(262144, 48000);
+        let mut output = Vec::new();
+        (0..262144).for_each(|i| {
+            let sample = (10000.0
+                * (i as f64 * 2.0 * std::f64::consts::PI * 40000.0 / 262144.0).sin())
+                as i16;
+            resampler.push((sample, sample), &mut output);
+        });
+        assert!(output[1000..].iter().all(|sample| sample.abs() < 100));
+    }
+}
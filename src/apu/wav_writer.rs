@@ -0,0 +1,108 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Size of the RIFF header, the format chunk and the header of the data chunk
+const HEADER_SIZE: u32 = 44;
+
+/// wav_writer::WavWriter
+///
+/// 16 bits PCM WAV file, written as the samples arrive. The sizes in the header are only known
+/// once the recording is over, and are filled by `finish`.
+pub struct WavWriter {
+    writer: BufWriter<File>,
+    channels: u16,
+    frames: u32,
+}
+
+impl WavWriter {
+    /// WavWriter::create
+    ///
+    /// @param path [&Path]: path of the file, replaced if it exists
+    /// @param channels [u16]: number of channels, 1 for mono and 2 for stereo
+    /// @param sample_rate [u32]: frames per second
+    /// @return [io::Result<WavWriter>]: writer, or the error creating the file
+    pub fn create(path: &Path, channels: u16, sample_rate: u32) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        let block_align = channels * 2;
+
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&(HEADER_SIZE - 8).to_le_bytes())?;
+        writer.write_all(b"WAVEfmt ")?;
+        writer.write_all(&16_u32.to_le_bytes())?;
+        writer.write_all(&1_u16.to_le_bytes())?; // PCM
+        writer.write_all(&channels.to_le_bytes())?;
+        writer.write_all(&sample_rate.to_le_bytes())?;
+        writer.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+        writer.write_all(&block_align.to_le_bytes())?;
+        writer.write_all(&16_u16.to_le_bytes())?;
+        writer.write_all(b"data")?;
+        writer.write_all(&0_u32.to_le_bytes())?;
+
+        Ok(Self {
+            writer,
+            channels,
+            frames: 0,
+        })
+    }
+
+    /// WavWriter::write_frame
+    ///
+    /// @param samples [&[i16]]: one sample per channel
+    /// @return [io::Result<()>]: error writing the file, if any
+    pub fn write_frame(&mut self, samples: &[i16]) -> io::Result<()> {
+        debug_assert_eq!(samples.len(), self.channels as usize);
+        for sample in samples {
+            self.writer.write_all(&sample.to_le_bytes())?;
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// WavWriter::finish
+    ///
+    /// Write the sizes of the chunks in the header, and close the file.
+    ///
+    /// @return [io::Result<()>]: error writing the file, if any
+    pub fn finish(mut self) -> io::Result<()> {
+        let data_size = self.frames * self.channels as u32 * 2;
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer
+            .write_all(&(HEADER_SIZE - 8 + data_size).to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(HEADER_SIZE as u64 - 4))?;
+        self.writer.write_all(&data_size.to_le_bytes())?;
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod test_wav_writer {
+
+    use crate::apu::wav_writer::WavWriter;
+
+    #[test]
+    fn test_write() {
+        let path = std::env::temp_dir().join("crusty_gba_test_wav_writer.wav");
+        let mut wav = WavWriter::create(&path, 2, 32768).unwrap();
+        wav.write_frame(&[1, -1]).unwrap();
+        wav.write_frame(&[0x1234, -0x8000]).unwrap();
+        wav.finish().unwrap();
+
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(bytes.len(), 44 + 8);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(bytes[4..8], 44_u32.to_le_bytes());
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(bytes[22..24], 2_u16.to_le_bytes());
+        assert_eq!(bytes[24..28], 32768_u32.to_le_bytes());
+        assert_eq!(bytes[28..32], (32768_u32 * 4).to_le_bytes());
+        assert_eq!(&bytes[36..40], b"data");
+        assert_eq!(bytes[40..44], 8_u32.to_le_bytes());
+        assert_eq!(
+            bytes[44..],
+            [0x01, 0x00, 0xff, 0xff, 0x34, 0x12, 0x00, 0x80]
+        );
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -386,6 +386,7 @@ impl ARM7TDMI {
             };
 
             req.address = address_to_mem;
+            req.n_opc = BusSignal::HIGH;
 
             // If the instruction requires a pre-modification, use the modify value as address.
             if p_flag == 1 {
@@ -470,9 +471,12 @@ impl ARM7TDMI {
                 req.data = self.rf.get_register(rd, 12);
 
                 // If only one byte is to be moved, copy the byte over all the 32 lines of the bus.
+                // Word stores ignore the 2 lsbs of the address.
                 if b_flag == 1 {
                     let byte = req.data & 0xff;
                     req.data = byte | (byte << 8) | (byte << 16) | (byte << 24);
+                } else {
+                    req.address &= !3;
                 }
 
                 // Post increment of the base register either if p is zero or if p is 1 and w is
@@ -551,6 +555,7 @@ impl ARM7TDMI {
             } else {
                 address_to_mem
             };
+            req.n_opc = BusSignal::HIGH;
         }
 
         // Load instruction
@@ -584,8 +589,8 @@ impl ARM7TDMI {
                     data_to_write = ((data_to_write as i8) as i32) as u32;
                 } else {
                     // If we are requiring the upper halfword of a word-aligned address, then get
-                    // the 16 msbs. Otherwise the 16 lsbs. Since the address used is always (?) a
-                    // multiple of 2, then offset is either 2 or 0.
+                    // the 16 msbs. Otherwise the 16 lsbs. On an odd address, the halfword is
+                    // rotated by 8 bits, so that the addressed byte ends in the 8 lsbs.
                     data_to_write = if offset < 2 {
                         data_to_write.get_range(15, 0)
                     } else {
@@ -642,7 +647,8 @@ impl ARM7TDMI {
                 } else if self.instruction_step == InstructionStep::STEP1 {
                     req.mas = TransferSize::HALFWORD;
 
-                    // get the data
+                    // get the data, while the lsb of the address is ignored
+                    req.address &= !1;
                     req.data = self.rf.get_register(rd, 12);
                     req.data = (req.data & 0xffff) | (req.data << 16);
 
@@ -1234,6 +1240,7 @@ impl ARM7TDMI {
                 req.address = self.list_transfer_op[self.instruction_counter_step as usize].0;
                 req.mas = TransferSize::WORD;
                 req.nr_w = BusSignal::HIGH;
+                req.n_opc = BusSignal::HIGH;
 
                 // Use user data or general data depending on s_flag
                 req.data = if s_flag == 1 {
@@ -1277,6 +1284,7 @@ impl ARM7TDMI {
                 req.bus_cycle = BusCycle::SEQUENTIAL;
                 req.address = self.list_transfer_op[0].0;
                 req.mas = TransferSize::WORD;
+                req.n_opc = BusSignal::HIGH;
                 if items_to_handle == 1 {
                     req.bus_cycle = BusCycle::INTERNAL;
                 }
@@ -1326,6 +1334,7 @@ impl ARM7TDMI {
                     }
                     req.address = self.list_transfer_op[self.instruction_counter_step as usize].0;
                     req.mas = TransferSize::WORD;
+                    req.n_opc = BusSignal::HIGH;
                     self.instruction_counter_step += 1;
                 }
             } else if self.instruction_step == InstructionStep::STEP3 {
@@ -141,6 +141,48 @@ mod cpu_test {
         assert_eq!(cpu.rf.get_register(10, 0), 0xcc);
     }
 
+    #[test]
+    fn load_store_misaligned_test() {
+        let mut cpu = ARM7TDMI::new();
+
+        let mut instructions = HashMap::from([
+            (0x00000030_u32, 0xaabbccdd_u32),
+            (0x08000000_u32, NOP),            // <--- entry point
+            (0x08000004_u32, 0xe5901031_u32), // ldr r1, [r0, 0x31]
+            (0x08000008_u32, 0xe1d023b1_u32), // ldrh r2, [r0, 0x31]
+            (0x0800000c_u32, 0xe1d033f3_u32), // ldrsh r3, [r0, 0x33]
+            (0x08000010_u32, 0xe5801042_u32), // str r1, [r0, 0x42]
+            (0x08000014_u32, 0xe1c024b7_u32), // strh r2, [r0, 0x47]
+            (0x08000018_u32, 0xeafffffe_u32), // b .
+        ]);
+        let mut response = MemoryResponse {
+            data: NOP,
+            n_wait: BusSignal::HIGH,
+        };
+
+        for _ in 0..50 {
+            let req = cpu.step(response);
+            if req.nr_w == BusSignal::LOW {
+                response.data = *instructions
+                    .get(&(req.address & 0xFFFFFFFC))
+                    .unwrap_or(&NOP);
+            } else {
+                instructions.insert(req.address, req.data);
+            }
+        }
+
+        // Loads rotate the data by the byte offset, ldrsh on an odd address sign-extends a byte
+        assert_eq!(cpu.rf.get_register(1, 0), 0xddaabbcc);
+        assert_eq!(cpu.rf.get_register(2, 0), 0xdd0000cc);
+        assert_eq!(cpu.rf.get_register(3, 0), 0xffffffaa);
+
+        // Stores are aligned to the size of the transfer
+        assert_eq!(*instructions.get(&0x40).unwrap_or(&0), 0xddaabbcc);
+        assert_eq!(*instructions.get(&0x46).unwrap_or(&0), 0x00cc00cc);
+        assert!(!instructions.contains_key(&0x42));
+        assert!(!instructions.contains_key(&0x47));
+    }
+
     #[test]
     fn load_store_hw_test() {
         let mut cpu = ARM7TDMI::new();
@@ -909,6 +951,58 @@ mod cpu_test {
         assert_eq!(cpu.rf.get_register(7, 0), 0xffffa304);
     }
 
+    #[test]
+    fn thumb_load_store_misaligned() {
+        let mut cpu = ARM7TDMI::new();
+
+        // 0x30 -> 0xaabbccdd
+        //
+        //  mov r1, 0x31
+        //  ldr r2, [r0, r1]  -> r2 == 0xddaabbcc
+        //  ldrh r3, [r0, r1] -> r3 == 0xdd0000cc
+        //  ldsh r4, [r0, r1] -> r4 == 0xffffffcc
+        //  mov r5, 0x43
+        //  str r2, [r0, r5]  -> [0x40] == 0xddaabbcc
+        //  strh r3, [r0, r5] -> [0x42] == 0x00cc
+        //  b .
+
+        let mut instructions = HashMap::from([
+            (0x00000030_u32, 0xaabbccdd),
+            (0x00100000_u32, 0x5842_2131),
+            (0x00100004_u32, 0x5e44_5a43),
+            (0x00100008_u32, 0x5142_2543),
+            (0x0010000c_u32, 0xe7fe_5343),
+            (0x08000000_u32, NOP),
+            (0x08000004_u32, 0xE3A0A601_u32),
+            (0x08000008_u32, 0xE28AA001_u32),
+            (0x0800000c_u32, 0xE3A0D000_u32),
+            (0x08000010_u32, 0xE12FFF1A_u32), // bx 0x00100000
+        ]);
+
+        let mut response = MemoryResponse {
+            data: NOP,
+            n_wait: BusSignal::HIGH,
+        };
+
+        for _ in 0..100 {
+            let req = cpu.step(response);
+            if req.nr_w == BusSignal::LOW {
+                response.data = *instructions
+                    .get(&(req.address & 0xFFFFFFFC))
+                    .unwrap_or(&NOP_THUMB);
+            } else {
+                instructions.insert(req.address, req.data);
+            }
+        }
+
+        assert_eq!(cpu.rf.get_register(2, 0), 0xddaabbcc);
+        assert_eq!(cpu.rf.get_register(3, 0), 0xdd0000cc);
+        assert_eq!(cpu.rf.get_register(4, 0), 0xffffffcc);
+        assert_eq!(*instructions.get(&0x40).unwrap_or(&0), 0xddaabbcc);
+        assert_eq!(*instructions.get(&0x42).unwrap_or(&0) & 0xffff, 0x00cc);
+        assert!(!instructions.contains_key(&0x43));
+    }
+
     #[test]
     fn thumb_branch() {
         let mut cpu = ARM7TDMI::new();
@@ -77,6 +77,13 @@ impl ARM7TDMI {
         }
     }
 
+    /// ARM7TDMI::reset
+    ///
+    /// Bring the cpu back to its post-reset state, with an empty pipeline.
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
     /// ARM7TDMI::step
     ///
     /// Corresponds to one clock cycle for the cpu.
@@ -37,6 +37,13 @@ use crate::common::BitOperation;
 /// --          SPSR_fiq  SPSR_svc   SPSR_abt  SPSR_irq  SPSR_und
 /// --------------------------------------------------------------
 
+/// Address of the first instruction executed after reset
+const RESET_PC: u32 = 0x08000000;
+/// Stack pointers set up by the bios
+const RESET_SP_USR: u32 = 0x03007f00;
+const RESET_SP_IRQ: u32 = 0x03007fa0;
+const RESET_SP_SVC: u32 = 0x03007fe0;
+
 /// register_file::ConditionCodeFlag
 ///
 /// enum to represent the 4 available flags in cpsr [manual, 2.13]
@@ -66,31 +73,54 @@ pub struct RegisterFile {
 impl RegisterFile {
     /// RegisterFile::new
     ///
-    /// Create the empty register file, with all the registers set to 0.
-    /// This behaviour might not be the definitive one.
+    /// Create the register file in the state it has after reset, see `RegisterFile::reset`.
     pub fn new() -> Self {
-        let mut registers = vec![0; 16];
-
-        // r15 gets this value so that the first instruction to be fetched is at the expected
-        // address
-        registers[15] = 0x07fffff8;
-
-        // r14 is initialized with the start vector
-        registers[14] = 0x08000000;
-
-        // r13 is initialized by the bios with a default value in iwram. As long as the bios is not
-        // implemented, this value shuold be fixed
-        registers[13] = 0x03007f00;
-        Self {
-            registers,
+        let mut rf = Self {
+            registers: vec![0; 16],
             fiq_bank: vec![0; 7],
             svc_bank: vec![0; 2],
             abt_bank: vec![0; 2],
             irq_bank: vec![0; 2],
             und_bank: vec![0; 2],
-            cpsr: 0x0000001f,
+            cpsr: 0,
             spsr: vec![0; 5],
-        }
+        };
+        rf.reset();
+        rf
+    }
+
+    /// RegisterFile::reset
+    ///
+    /// Bring the registers to the state the bios leaves them in before jumping to the game pak,
+    /// since the boot sequence is skipped. From gbatek/bios-ram-usage:
+    ///
+    /// Register    Value
+    /// -------------------------------------------------------
+    /// CPSR        0x0000001f (system mode, arm state, interrupts enabled)
+    /// R13_usr     0x03007f00
+    /// R13_irq     0x03007fa0
+    /// R13_svc     0x03007fe0
+    /// R14_usr     0x08000000 (start vector)
+    /// R15         0x08000000 (first fetch)
+    /// others      0
+    /// -------------------------------------------------------
+    pub fn reset(&mut self) {
+        self.registers.fill(0);
+        self.fiq_bank.fill(0);
+        self.svc_bank.fill(0);
+        self.abt_bank.fill(0);
+        self.irq_bank.fill(0);
+        self.und_bank.fill(0);
+        self.spsr.fill(0);
+
+        // r15 gets this value so that the first instruction to be fetched is at the expected
+        // address
+        self.registers[15] = RESET_PC.wrapping_sub(8);
+        self.registers[14] = RESET_PC;
+        self.registers[13] = RESET_SP_USR;
+        self.irq_bank[0] = RESET_SP_IRQ;
+        self.svc_bank[0] = RESET_SP_SVC;
+        self.cpsr = OperatingMode::SYSTEM as u32;
     }
 
     /// RegisterFile::get_register
@@ -524,4 +554,21 @@ mod test_register_file {
         // always
         assert_eq!(true, rf.check_condition_code(0b1110));
     }
+
+    #[test]
+    fn test_reset() {
+        let mut rf = RegisterFile::new();
+        rf.write_register(0, 0x1234);
+        assert_eq!(rf.write_cpsr(OperatingMode::IRQ as u32), Ok(()));
+
+        rf.reset();
+        assert_eq!(rf.get_mode(), OperatingMode::SYSTEM);
+        assert_eq!(rf.get_register(0, 0), 0);
+        assert_eq!(rf.get_register(13, 0), 0x03007f00);
+        assert_eq!(rf.get_register(15, 8), 0x08000000);
+        assert_eq!(rf.write_cpsr(OperatingMode::IRQ as u32), Ok(()));
+        assert_eq!(rf.get_register(13, 0), 0x03007fa0);
+        assert_eq!(rf.write_cpsr(OperatingMode::SUPERVISOR as u32), Ok(()));
+        assert_eq!(rf.get_register(13, 0), 0x03007fe0);
+    }
 }
@@ -0,0 +1,242 @@
+use crate::memory::Memory;
+
+/// Size of the largest EEPROM, 8KB (the 512B chip only uses the beginning of the storage)
+pub const EEPROM_MAX_SIZE: u32 = 0x2000;
+
+/// Number of bits returned by a read request: 4 ignored bits followed by a 64 bits block
+const READ_BITS: u32 = 68;
+
+/// eeprom::Eeprom
+///
+/// Serial EEPROM of the game pak, accessed one bit at a time (bit 0 of each halfword) at the end
+/// of the ROM region, usually through DMA3. The memory is organized in 64 bits blocks, and each
+/// request starts with a 2 bits command followed by the block address:
+///
+/// Request     Bits sent by the game                      Bits returned
+/// -----------------------------------------------------------------------------------
+/// Read        11, address, 0                             4 ignored bits, 64 data bits
+/// Write       10, address, 64 data bits, 0               ready bit (1)
+/// -----------------------------------------------------------------------------------
+///
+/// The address is 6 bits wide on the 512B chips and 14 bits wide (of which only the lower 10 are
+/// used) on the 8KB chips. The width is not known in advance: it is deduced from the length of
+/// the first DMA transfer towards the chip, or from the size of the save file.
+pub struct Eeprom {
+    pub memory: Memory,
+    address_bits: Option<u32>,
+    request: u32,        // Command and address received so far
+    request_length: u32, // Number of bits received for the current request
+    block: u64,          // Data received by a write request
+    read_address: u32,   // Byte offset of the block being returned by a read request
+    read_index: Option<u32>,
+}
+
+impl Eeprom {
+    pub fn new() -> Self {
+        let mut memory = Memory::new(0, EEPROM_MAX_SIZE, false, String::from("EEPROM"));
+        memory.as_bytes_mut().fill(0xff);
+
+        Self {
+            memory,
+            address_bits: None,
+            request: 0,
+            request_length: 0,
+            block: 0,
+            read_address: 0,
+            read_index: None,
+        }
+    }
+
+    /// Eeprom::detect_address_bits
+    ///
+    /// Deduce the width of the address from the length of a DMA transfer towards the chip. Only
+    /// the first request which can tell the two chips apart is taken into account.
+    ///
+    /// @param dma_length [u32]: number of halfwords of the transfer
+    pub fn detect_address_bits(&mut self, dma_length: u32) {
+        if self.address_bits.is_some() {
+            return;
+        }
+
+        self.address_bits = match dma_length {
+            9 | 73 => Some(6),
+            17 | 81 => Some(14),
+            _ => None,
+        };
+    }
+
+    /// Eeprom::set_save_size
+    ///
+    /// Select the chip matching the size of an existing save file.
+    ///
+    /// @param size [u64]: size of the save file, in bytes
+    pub fn set_save_size(&mut self, size: u64) {
+        self.address_bits = Some(if size <= 512 { 6 } else { 14 });
+    }
+
+    /// Eeprom::size
+    ///
+    /// @return [u32]: size of the chip, in bytes. The 8KB chip is assumed until the width of the
+    /// address is known.
+    pub fn size(&self) -> u32 {
+        match self.address_bits {
+            Some(6) => 0x200,
+            _ => EEPROM_MAX_SIZE,
+        }
+    }
+
+    /// Eeprom::read
+    ///
+    /// @return [u32]: next bit of a read request, or the ready bit, on bit 0 of each halfword
+    pub fn read(&mut self) -> u32 {
+        let Some(index) = self.read_index else {
+            return 0x00010001;
+        };
+
+        self.read_index = if index + 1 < READ_BITS {
+            Some(index + 1)
+        } else {
+            None
+        };
+
+        if index < READ_BITS - 64 {
+            return 0;
+        }
+
+        let bit = index - (READ_BITS - 64);
+        let byte = self.memory.read8(self.read_address + bit / 8);
+        ((byte >> (7 - bit % 8)) & 1) as u32 * 0x00010001
+    }
+
+    /// Eeprom::write
+    ///
+    /// @param data [u32]: data on the bus, of which only bit 0 is used
+    pub fn write(&mut self, data: u32) {
+        let bit = data & 1;
+        let address_bits = self.address_bits.unwrap_or(14);
+        let header_length = 2 + address_bits;
+
+        self.request_length += 1;
+        if self.request_length == 1 {
+            // A new request aborts any read in progress
+            self.read_index = None;
+            self.request = 0;
+            self.block = 0;
+        }
+
+        if self.request_length <= header_length {
+            self.request = (self.request << 1) | bit;
+            return;
+        }
+
+        let is_read = self.request >> address_bits == 0b11;
+        let block_address = self.request & ((1 << address_bits) - 1) & 0x3ff;
+        let byte_address = (block_address * 8) & (self.size() - 1);
+
+        if is_read {
+            // Stop bit
+            self.read_address = byte_address;
+            self.read_index = Some(0);
+            self.request_length = 0;
+        } else if self.request_length <= header_length + 64 {
+            self.block = (self.block << 1) | bit as u64;
+        } else {
+            // Stop bit
+            for (i, byte) in self.block.to_be_bytes().iter().enumerate() {
+                self.memory.write8(byte_address + i as u32, *byte);
+            }
+            self.request_length = 0;
+        }
+    }
+
+    /// Eeprom::data
+    ///
+    /// @return [&[u8]]: content of the chip, as stored in save files
+    pub fn data(&self) -> &[u8] {
+        &self.memory.as_bytes()[..self.size() as usize]
+    }
+
+    /// Eeprom::data_mut
+    ///
+    /// @return [&mut [u8]]: content of the chip, as stored in save files
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        let size = self.size() as usize;
+        &mut self.memory.as_bytes_mut()[..size]
+    }
+}
+
+#[cfg(test)]
+mod test_eeprom {
+
+    use crate::backup::eeprom::Eeprom;
+
+    fn send(eeprom: &mut Eeprom, value: u64, bits: u32) {
+        (0..bits)
+            .rev()
+            .for_each(|i| eeprom.write(((value >> i) & 1) as u32 * 0x00010001));
+    }
+
+    fn receive(eeprom: &mut Eeprom) -> u64 {
+        (0..4).for_each(|_| assert_eq!(eeprom.read() & 1, 0));
+        (0..64).fold(0, |value, _| (value << 1) | (eeprom.read() & 1) as u64)
+    }
+
+    #[test]
+    fn test_eeprom_512b() {
+        let mut eeprom = Eeprom::new();
+        eeprom.detect_address_bits(73);
+        assert_eq!(eeprom.size(), 0x200);
+
+        // Write request to block 3, followed by the stop bit
+        send(&mut eeprom, 0b10, 2);
+        send(&mut eeprom, 3, 6);
+        send(&mut eeprom, 0x0123456789abcdef, 64);
+        send(&mut eeprom, 0, 1);
+        assert_eq!(eeprom.read(), 0x00010001);
+        assert_eq!(
+            eeprom.data()[0x18..0x20],
+            [0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef]
+        );
+
+        // Read request to block 3
+        send(&mut eeprom, 0b11, 2);
+        send(&mut eeprom, 3, 6);
+        send(&mut eeprom, 0, 1);
+        assert_eq!(receive(&mut eeprom), 0x0123456789abcdef);
+        assert_eq!(eeprom.read(), 0x00010001);
+
+        // Erased blocks read as all ones
+        send(&mut eeprom, 0b11, 2);
+        send(&mut eeprom, 4, 6);
+        send(&mut eeprom, 0, 1);
+        assert_eq!(receive(&mut eeprom), u64::MAX);
+
+        // Later transfers do not change the detected size
+        eeprom.detect_address_bits(17);
+        assert_eq!(eeprom.data().len(), 0x200);
+    }
+
+    #[test]
+    fn test_eeprom_8kb() {
+        let mut eeprom = Eeprom::new();
+        eeprom.detect_address_bits(68);
+        eeprom.detect_address_bits(17);
+        assert_eq!(eeprom.size(), 0x2000);
+
+        // Only the lower 10 bits of the address are used
+        send(&mut eeprom, 0b10, 2);
+        send(&mut eeprom, 0x401, 14);
+        send(&mut eeprom, 0xfedcba9876543210, 64);
+        send(&mut eeprom, 0, 1);
+        assert_eq!(eeprom.data()[8], 0xfe);
+
+        send(&mut eeprom, 0b11, 2);
+        send(&mut eeprom, 1, 14);
+        send(&mut eeprom, 0, 1);
+        assert_eq!(receive(&mut eeprom), 0xfedcba9876543210);
+
+        let mut eeprom = Eeprom::new();
+        eeprom.set_save_size(512);
+        assert_eq!(eeprom.data_mut().len(), 0x200);
+    }
+}
@@ -0,0 +1,273 @@
+use crate::memory::Memory;
+
+/// Address of the flash region
+pub const FLASH_INIT_ADDR: u32 = 0x0e000000;
+/// Size of a bank, which is the part of the chip visible in the region
+const BANK_SIZE: u32 = 0x10000;
+/// Size of the blocks erased by the sector erase command
+const SECTOR_SIZE: u32 = 0x1000;
+
+/// Addresses and data of the unlock sequence preceding each command
+const UNLOCK_ADDRESS_1: u32 = 0x5555;
+const UNLOCK_ADDRESS_2: u32 = 0x2aaa;
+const UNLOCK_DATA_1: u8 = 0xaa;
+const UNLOCK_DATA_2: u8 = 0x55;
+
+/// Commands of the chip, written to UNLOCK_ADDRESS_1 after the unlock sequence (apart from the
+/// sector erase, written to the sector address)
+const COMMAND_ENTER_ID_MODE: u8 = 0x90;
+const COMMAND_EXIT_ID_MODE: u8 = 0xf0;
+const COMMAND_PREPARE_ERASE: u8 = 0x80;
+const COMMAND_ERASE_CHIP: u8 = 0x10;
+const COMMAND_ERASE_SECTOR: u8 = 0x30;
+const COMMAND_PROGRAM_BYTE: u8 = 0xa0;
+const COMMAND_SWITCH_BANK: u8 = 0xb0;
+
+/// flash::FlashChip
+///
+/// Flash chips used in gba game paks, from gbatek/gba-cart-backup-flash-rom.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FlashChip {
+    Macronix64K,  // MX29L512
+    Panasonic64K, // MN63F805MNP
+    Sst64K,       // SST39VF512
+    Macronix128K, // MX29L010
+    Sanyo128K,    // LE26FV10N1TS
+}
+
+impl FlashChip {
+    /// FlashChip::ids
+    ///
+    /// @return [(u8, u8)]: manufacturer and device ids returned in id mode
+    pub fn ids(&self) -> (u8, u8) {
+        match self {
+            FlashChip::Macronix64K => (0xc2, 0x1c),
+            FlashChip::Panasonic64K => (0x32, 0x1b),
+            FlashChip::Sst64K => (0xbf, 0xd4),
+            FlashChip::Macronix128K => (0xc2, 0x09),
+            FlashChip::Sanyo128K => (0x62, 0x13),
+        }
+    }
+
+    /// FlashChip::size
+    ///
+    /// @return [u32]: size of the chip in bytes
+    pub fn size(&self) -> u32 {
+        match self {
+            FlashChip::Macronix128K | FlashChip::Sanyo128K => 2 * BANK_SIZE,
+            _ => BANK_SIZE,
+        }
+    }
+}
+
+/// flash::FlashState
+///
+/// Position in the command protocol of the chip.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum FlashState {
+    Ready,
+    Unlock1,     // First byte of the unlock sequence received
+    Unlock2,     // Unlock sequence received, waiting for a command
+    ProgramByte, // Waiting for the byte to program
+    SwitchBank,  // Waiting for the bank number
+}
+
+/// flash::Flash
+///
+/// Flash backup memory. The chip is read as a normal 8 bits memory, while it is modified through
+/// commands written after an unlock sequence. 128KB chips are split in two banks of 64KB, of which
+/// only one is visible at a time.
+pub struct Flash {
+    pub memory: Memory,
+    chip: FlashChip,
+    state: FlashState,
+    erase_prepared: bool,
+    id_mode: bool,
+    bank: u32,
+}
+
+impl Flash {
+    /// Flash::new
+    ///
+    /// Create an erased chip.
+    ///
+    /// @param chip [FlashChip]: chip to emulate
+    pub fn new(chip: FlashChip) -> Self {
+        let mut memory = Memory::new(FLASH_INIT_ADDR, chip.size(), false, String::from("FLASH"));
+        memory.as_bytes_mut().fill(0xff);
+
+        Self {
+            memory,
+            chip,
+            state: FlashState::Ready,
+            erase_prepared: false,
+            id_mode: false,
+            bank: 0,
+        }
+    }
+
+    /// Flash::read
+    ///
+    /// @param address [u32]: address of the access
+    /// @return [u32]: byte read, repeated on the 4 lanes of the bus
+    pub fn read(&self, address: u32) -> u32 {
+        let offset = address & (BANK_SIZE - 1);
+        let (manufacturer, device) = self.chip.ids();
+
+        let byte = match offset {
+            0 if self.id_mode => manufacturer,
+            1 if self.id_mode => device,
+            _ => self.memory.read8(self.chip_address(offset)),
+        };
+        byte as u32 * 0x01010101
+    }
+
+    /// Flash::write
+    ///
+    /// Advance the command protocol with a byte written by the cpu.
+    ///
+    /// @param address [u32]: address of the access
+    /// @param data [u32]: data on the bus
+    pub fn write(&mut self, address: u32, data: u32) {
+        let offset = address & (BANK_SIZE - 1);
+        let byte = (data >> ((address & 3) * 8)) as u8;
+
+        self.state = match (self.state, offset, byte) {
+            (FlashState::Ready, UNLOCK_ADDRESS_1, UNLOCK_DATA_1) => FlashState::Unlock1,
+            (FlashState::Unlock1, UNLOCK_ADDRESS_2, UNLOCK_DATA_2) => FlashState::Unlock2,
+            (FlashState::Unlock2, _, _) => self.command(offset, byte),
+            (FlashState::ProgramByte, _, _) => {
+                let chip_address = self.chip_address(offset);
+                self.memory.write8(chip_address, byte);
+                FlashState::Ready
+            }
+            (FlashState::SwitchBank, 0, _) => {
+                self.bank = byte as u32 & 1;
+                FlashState::Ready
+            }
+            _ => FlashState::Ready,
+        };
+    }
+
+    /// Flash::command
+    ///
+    /// Execute a command received after the unlock sequence.
+    ///
+    /// @param offset [u32]: offset of the command in the bank
+    /// @param command [u8]: command
+    /// @return [FlashState]: next state of the protocol
+    fn command(&mut self, offset: u32, command: u8) -> FlashState {
+        let erase_prepared = self.erase_prepared;
+        self.erase_prepared = false;
+
+        match (offset, command) {
+            (_, COMMAND_ERASE_SECTOR) if erase_prepared => {
+                let sector = self.chip_address(offset & !(SECTOR_SIZE - 1));
+                self.memory.slice_mut(sector, SECTOR_SIZE).fill(0xff);
+            }
+            (UNLOCK_ADDRESS_1, COMMAND_ERASE_CHIP) if erase_prepared => {
+                self.memory.as_bytes_mut().fill(0xff);
+            }
+            (UNLOCK_ADDRESS_1, COMMAND_PREPARE_ERASE) => self.erase_prepared = true,
+            (UNLOCK_ADDRESS_1, COMMAND_ENTER_ID_MODE) => self.id_mode = true,
+            (UNLOCK_ADDRESS_1, COMMAND_EXIT_ID_MODE) => self.id_mode = false,
+            (UNLOCK_ADDRESS_1, COMMAND_PROGRAM_BYTE) => return FlashState::ProgramByte,
+            (UNLOCK_ADDRESS_1, COMMAND_SWITCH_BANK) if self.chip.size() > BANK_SIZE => {
+                return FlashState::SwitchBank
+            }
+            _ => {}
+        }
+        FlashState::Ready
+    }
+
+    /// Flash::chip_address
+    ///
+    /// @param offset [u32]: offset in the visible bank
+    /// @return [u32]: address of the byte in the memory of the chip
+    fn chip_address(&self, offset: u32) -> u32 {
+        FLASH_INIT_ADDR + self.bank * BANK_SIZE + offset
+    }
+}
+
+#[cfg(test)]
+mod test_flash {
+
+    use crate::backup::flash::{Flash, FlashChip};
+
+    // The cpu copies the byte of strb over the 4 lanes of the bus
+    fn write_byte(flash: &mut Flash, address: u32, byte: u32) {
+        flash.write(address, byte * 0x01010101);
+    }
+
+    fn command(flash: &mut Flash, address: u32, command: u32) {
+        write_byte(flash, 0x0e005555, 0xaa);
+        write_byte(flash, 0x0e002aaa, 0x55);
+        write_byte(flash, address, command);
+    }
+
+    #[test]
+    fn test_flash_ids() {
+        let mut flash = Flash::new(FlashChip::Macronix128K);
+
+        command(&mut flash, 0x0e005555, 0x90);
+        assert_eq!(flash.read(0x0e000000), 0xc2c2c2c2);
+        assert_eq!(flash.read(0x0e000001) & 0xff, 0x09);
+        command(&mut flash, 0x0e005555, 0xf0);
+        assert_eq!(flash.read(0x0e000000), 0xffffffff);
+
+        let mut flash = Flash::new(FlashChip::Panasonic64K);
+        command(&mut flash, 0x0e005555, 0x90);
+        assert_eq!(flash.read(0x0e000001) & 0xff, 0x1b);
+    }
+
+    #[test]
+    fn test_flash_program_and_erase() {
+        let mut flash = Flash::new(FlashChip::Sst64K);
+
+        // Writes without the command sequence are ignored
+        write_byte(&mut flash, 0x0e000010, 0x12);
+        assert_eq!(flash.read(0x0e000010) & 0xff, 0xff);
+
+        command(&mut flash, 0x0e005555, 0xa0);
+        write_byte(&mut flash, 0x0e000010, 0x12);
+        command(&mut flash, 0x0e005555, 0xa0);
+        write_byte(&mut flash, 0x0e001010, 0x34);
+        assert_eq!(flash.read(0x0e000010) & 0xff, 0x12);
+        assert_eq!(flash.read(0x0e001010) & 0xff, 0x34);
+
+        // Sector erase only affects the addressed 4KB sector
+        command(&mut flash, 0x0e005555, 0x80);
+        command(&mut flash, 0x0e001000, 0x30);
+        assert_eq!(flash.read(0x0e000010) & 0xff, 0x12);
+        assert_eq!(flash.read(0x0e001010) & 0xff, 0xff);
+
+        // Chip erase
+        command(&mut flash, 0x0e005555, 0x80);
+        command(&mut flash, 0x0e005555, 0x10);
+        assert_eq!(flash.read(0x0e000010) & 0xff, 0xff);
+
+        // Erase commands require the erase to be prepared
+        command(&mut flash, 0x0e005555, 0xa0);
+        write_byte(&mut flash, 0x0e000010, 0x12);
+        command(&mut flash, 0x0e005555, 0x10);
+        assert_eq!(flash.read(0x0e000010) & 0xff, 0x12);
+    }
+
+    #[test]
+    fn test_flash_banks() {
+        let mut flash = Flash::new(FlashChip::Sanyo128K);
+
+        command(&mut flash, 0x0e005555, 0xa0);
+        write_byte(&mut flash, 0x0e000020, 0x01);
+        command(&mut flash, 0x0e005555, 0xb0);
+        write_byte(&mut flash, 0x0e000000, 0x01);
+        assert_eq!(flash.read(0x0e000020) & 0xff, 0xff);
+        command(&mut flash, 0x0e005555, 0xa0);
+        write_byte(&mut flash, 0x0e000020, 0x02);
+
+        command(&mut flash, 0x0e005555, 0xb0);
+        write_byte(&mut flash, 0x0e000000, 0x00);
+        assert_eq!(flash.read(0x0e000020) & 0xff, 0x01);
+        assert_eq!(flash.memory.read_byte(0x0e010020), 0x02);
+    }
+}
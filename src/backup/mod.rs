@@ -0,0 +1,218 @@
+pub mod eeprom;
+pub mod flash;
+pub mod save_file;
+pub mod sram;
+
+use crate::backup::eeprom::Eeprom;
+use crate::backup::flash::{Flash, FlashChip};
+use crate::backup::sram::Sram;
+use crate::bus::TransferSize;
+use std::str::FromStr;
+
+/// backup::BackupType
+///
+/// Kind of backup memory of a game pak. Flash memories also give the chip, since some games
+/// check its ids.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum BackupType {
+    Sram,
+    Flash(FlashChip),
+    Eeprom,
+}
+
+/// Names of the flash chips in the save types. `flash64` and `flash128` select the chips which are
+/// most common among gba games for their size.
+const FLASH_NAMES: [(&str, FlashChip); 7] = [
+    ("flash64", FlashChip::Panasonic64K),
+    ("flash128", FlashChip::Sanyo128K),
+    ("flash64-macronix", FlashChip::Macronix64K),
+    ("flash64-panasonic", FlashChip::Panasonic64K),
+    ("flash64-sst", FlashChip::Sst64K),
+    ("flash128-macronix", FlashChip::Macronix128K),
+    ("flash128-sanyo", FlashChip::Sanyo128K),
+];
+
+/// Strings embedded in the rom by the save libraries of the Nintendo SDK, with the corresponding
+/// backup memory. They are followed by the version of the library (e.g. "FLASH1M_V103").
+const LIBRARY_MARKERS: [(&[u8], BackupType); 6] = [
+    (b"EEPROM_V", BackupType::Eeprom),
+    (b"SRAM_V", BackupType::Sram),
+    (b"SRAM_F_V", BackupType::Sram),
+    (b"FLASH_V", BackupType::Flash(FlashChip::Panasonic64K)),
+    (b"FLASH512_V", BackupType::Flash(FlashChip::Panasonic64K)),
+    (b"FLASH1M_V", BackupType::Flash(FlashChip::Sanyo128K)),
+];
+
+impl BackupType {
+    /// BackupType::detect
+    ///
+    /// Guess the backup memory of a game from the save library linked in its rom. The strings of
+    /// the libraries are word aligned.
+    ///
+    /// @param rom [&[u8]]: content of the rom
+    /// @return [Option<BackupType>]: kind of backup memory, if a library was found
+    pub fn detect(rom: &[u8]) -> Option<BackupType> {
+        (0..rom.len()).step_by(4).find_map(|offset| {
+            LIBRARY_MARKERS
+                .iter()
+                .find(|(marker, _)| rom[offset..].starts_with(marker))
+                .map(|(_, backup_type)| *backup_type)
+        })
+    }
+}
+
+impl FromStr for BackupType {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "sram" => Ok(BackupType::Sram),
+            "eeprom" => Ok(BackupType::Eeprom),
+            _ => FLASH_NAMES
+                .iter()
+                .find(|(flash_name, _)| *flash_name == name)
+                .map(|(_, chip)| BackupType::Flash(*chip))
+                .ok_or_else(|| {
+                    let flash_names: Vec<&str> =
+                        FLASH_NAMES.iter().map(|(name, _)| *name).collect();
+                    format!(
+                        "unknown save type {}, expected sram, eeprom or {}",
+                        name,
+                        flash_names.join(", ")
+                    )
+                }),
+        }
+    }
+}
+
+/// backup::Backup
+///
+/// Backup memory of the game pak, mapped at 0x0e000000 - 0x0fffffff. The EEPROM is instead
+/// accessed at the end of the ROM region, see `Bus::is_eeprom_address`.
+pub enum Backup {
+    Sram(Sram),
+    Flash(Flash),
+    Eeprom(Eeprom),
+}
+
+impl Backup {
+    /// Backup::new
+    ///
+    /// Create an empty backup memory.
+    ///
+    /// @param backup_type [BackupType]: kind of backup memory
+    pub fn new(backup_type: BackupType) -> Self {
+        match backup_type {
+            BackupType::Sram => Backup::Sram(Sram::new()),
+            BackupType::Flash(chip) => Backup::Flash(Flash::new(chip)),
+            BackupType::Eeprom => Backup::Eeprom(Eeprom::new()),
+        }
+    }
+
+    /// Backup::read
+    ///
+    /// @param address [u32]: address of the access, in 0x0e000000 - 0x0e00ffff
+    /// @return [u32]: data on the bus
+    pub fn read(&self, address: u32) -> u32 {
+        match self {
+            Backup::Sram(sram) => sram.read(address),
+            Backup::Flash(flash) => flash.read(address),
+            Backup::Eeprom(_) => 0xffffffff,
+        }
+    }
+
+    /// Backup::write
+    ///
+    /// @param address [u32]: address of the access, in 0x0e000000 - 0x0e00ffff
+    /// @param data [u32]: data on the bus
+    /// @param mas [TransferSize]: size of the transfer
+    pub fn write(&mut self, address: u32, data: u32, mas: TransferSize) {
+        match self {
+            Backup::Sram(sram) => sram.write(address, data, mas),
+            Backup::Flash(flash) => flash.write(address, data),
+            Backup::Eeprom(_) => {}
+        }
+    }
+
+    /// Backup::data
+    ///
+    /// @return [&[u8]]: content of the memory, as stored in save files
+    pub fn data(&self) -> &[u8] {
+        match self {
+            Backup::Sram(sram) => sram.memory.as_bytes(),
+            Backup::Flash(flash) => flash.memory.as_bytes(),
+            Backup::Eeprom(eeprom) => eeprom.data(),
+        }
+    }
+
+    /// Backup::data_mut
+    ///
+    /// @return [&mut [u8]]: content of the memory, as stored in save files
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        match self {
+            Backup::Sram(sram) => sram.memory.as_bytes_mut(),
+            Backup::Flash(flash) => flash.memory.as_bytes_mut(),
+            Backup::Eeprom(eeprom) => eeprom.data_mut(),
+        }
+    }
+
+    /// Backup::set_save_size
+    ///
+    /// Adapt the memory to the size of an existing save file, for the chips whose size cannot be
+    /// known in advance.
+    ///
+    /// @param size [u64]: size of the save file, in bytes
+    pub fn set_save_size(&mut self, size: u64) {
+        if let Backup::Eeprom(eeprom) = self {
+            eeprom.set_save_size(size);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_backup {
+
+    use crate::backup::flash::FlashChip;
+    use crate::backup::BackupType;
+
+    #[test]
+    fn test_detect() {
+        let mut rom = vec![0_u8; 0x100];
+        assert_eq!(BackupType::detect(&rom), None);
+
+        rom[0x40..0x4c].copy_from_slice(b"FLASH1M_V103");
+        assert_eq!(
+            BackupType::detect(&rom),
+            Some(BackupType::Flash(FlashChip::Sanyo128K))
+        );
+        rom[0x40..0x4c].copy_from_slice(b"FLASH512_V13");
+        assert_eq!(
+            BackupType::detect(&rom),
+            Some(BackupType::Flash(FlashChip::Panasonic64K))
+        );
+        rom[0x40..0x4c].copy_from_slice(b"SRAM_F_V102\0");
+        assert_eq!(BackupType::detect(&rom), Some(BackupType::Sram));
+        rom[0x40..0x4c].copy_from_slice(b"EEPROM_V124\0");
+        assert_eq!(BackupType::detect(&rom), Some(BackupType::Eeprom));
+
+        // Unaligned strings are not library markers
+        rom[0x40] = 0;
+        rom[0x41..0x4d].copy_from_slice(b"EEPROM_V124\0");
+        assert_eq!(BackupType::detect(&rom), None);
+
+        assert_eq!(
+            "flash64".parse(),
+            Ok(BackupType::Flash(FlashChip::Panasonic64K))
+        );
+        assert_eq!(
+            "flash64-sst".parse(),
+            Ok(BackupType::Flash(FlashChip::Sst64K))
+        );
+        assert_eq!(
+            "flash128-macronix".parse(),
+            Ok(BackupType::Flash(FlashChip::Macronix128K))
+        );
+        assert!("flash".parse::<BackupType>().is_err());
+        assert!("flash128-sst".parse::<BackupType>().is_err());
+    }
+}
@@ -0,0 +1,184 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Frequency of the system clock, in cycles per second
+const CPU_FREQUENCY: u32 = 16_777_216;
+/// Cycles without writes to the backup memory after which the save file is updated
+const AUTO_FLUSH_DELAY: u32 = 3 * CPU_FREQUENCY;
+
+/// save_file::SaveFile
+///
+/// File storing the content of the backup memory of the game pak between two sessions. Writes to
+/// the backup memory mark the file as dirty, and the file is flushed once the game has not
+/// written to the backup memory for `AUTO_FLUSH_DELAY` cycles, or when the emulator is closed.
+pub struct SaveFile {
+    path: PathBuf,
+    dirty: bool,
+    idle_cycles: u32,
+}
+
+impl SaveFile {
+    /// SaveFile::new
+    ///
+    /// @param path [PathBuf]: path of the save file
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            dirty: false,
+            idle_cycles: 0,
+        }
+    }
+
+    /// SaveFile::path_for_rom
+    ///
+    /// Saves are stored next to the rom, with the same name. In a dedicated directory, they are
+    /// named after the game code when the rom has a valid header, so that a game keeps its save
+    /// when the rom is renamed.
+    ///
+    /// @param rom_file [&str]: path of the rom
+    /// @param save_dir [Option<&str>]: directory of the saves, if not the one of the rom
+    /// @param game_code [Option<&str>]: game code from the header of the rom
+    /// @return [PathBuf]: path of the save file, with extension `.sav`
+    pub fn path_for_rom(
+        rom_file: &str,
+        save_dir: Option<&str>,
+        game_code: Option<&str>,
+    ) -> PathBuf {
+        let rom_path = Path::new(rom_file).with_extension("sav");
+        match (save_dir, game_code) {
+            (Some(dir), Some(code)) => Path::new(dir).join(code).with_extension("sav"),
+            (Some(dir), None) => Path::new(dir).join(rom_path.file_name().unwrap_or_default()),
+            (None, _) => rom_path,
+        }
+    }
+
+    /// SaveFile::path
+    ///
+    /// @return [&Path]: path of the save file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// SaveFile::size
+    ///
+    /// @return [Option<u64>]: size of the save file in bytes, if it exists
+    pub fn size(&self) -> Option<u64> {
+        fs::metadata(&self.path).ok().map(|metadata| metadata.len())
+    }
+
+    /// SaveFile::load
+    ///
+    /// Copy the content of the save file, if it exists, into the backup memory. Files having a
+    /// different size than the memory are truncated or padded.
+    ///
+    /// @param data [&mut [u8]]: content of the backup memory
+    /// @return [io::Result<bool>]: true if a save file was found
+    pub fn load(&self, data: &mut [u8]) -> io::Result<bool> {
+        let content = match fs::read(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e),
+        };
+
+        let len = content.len().min(data.len());
+        data[..len].copy_from_slice(&content[..len]);
+        Ok(true)
+    }
+
+    /// SaveFile::mark_dirty
+    ///
+    /// Signal a write to the backup memory.
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+        self.idle_cycles = 0;
+    }
+
+    /// SaveFile::step
+    ///
+    /// Advance the auto-flush timer by one cycle.
+    ///
+    /// @return [bool]: true if the file is to be flushed
+    pub fn step(&mut self) -> bool {
+        if !self.dirty {
+            return false;
+        }
+
+        self.idle_cycles += 1;
+        self.idle_cycles >= AUTO_FLUSH_DELAY
+    }
+
+    /// SaveFile::flush
+    ///
+    /// Write the content of the backup memory to the file, if it was modified. The content is
+    /// first written to a temporary file, so that a crash cannot leave a truncated save.
+    ///
+    /// @param data [&[u8]]: content of the backup memory
+    pub fn flush(&mut self, data: &[u8]) -> io::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let temp_path = self.path.with_extension("sav.tmp");
+        fs::write(&temp_path, data)?;
+        fs::rename(&temp_path, &self.path)?;
+
+        self.dirty = false;
+        self.idle_cycles = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test_save_file {
+
+    use crate::backup::save_file::{SaveFile, AUTO_FLUSH_DELAY};
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_path_for_rom() {
+        assert_eq!(
+            SaveFile::path_for_rom("roms/game.gba", None, Some("ATSE")),
+            PathBuf::from("roms/game.sav")
+        );
+        assert_eq!(
+            SaveFile::path_for_rom("roms/game.gba", Some("saves"), None),
+            PathBuf::from("saves/game.sav")
+        );
+        assert_eq!(
+            SaveFile::path_for_rom("roms/game.gba", Some("saves"), Some("ATSE")),
+            PathBuf::from("saves/ATSE.sav")
+        );
+    }
+
+    #[test]
+    fn test_load_and_flush() {
+        let dir = std::env::temp_dir().join("crusty_gba_test_save_file");
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut save_file = SaveFile::new(dir.join("game.sav"));
+
+        // No save file yet
+        let mut data = vec![0xff_u8; 8];
+        assert!(!save_file.load(&mut data).unwrap());
+        assert_eq!(save_file.size(), None);
+
+        // The file is flushed after some idle time, and only if it was modified
+        assert!(!save_file.step());
+        data[2] = 0x12;
+        save_file.mark_dirty();
+        (1..AUTO_FLUSH_DELAY).for_each(|_| assert!(!save_file.step()));
+        assert!(save_file.step());
+        save_file.flush(&data).unwrap();
+        assert!(!save_file.step());
+
+        let mut loaded = vec![0_u8; 4];
+        assert!(save_file.load(&mut loaded).unwrap());
+        assert_eq!(save_file.size(), Some(8));
+        assert_eq!(loaded, vec![0xff, 0xff, 0x12, 0xff]);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
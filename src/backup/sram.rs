@@ -0,0 +1,75 @@
+use crate::bus::TransferSize;
+use crate::memory::Memory;
+
+/// Address of the SRAM region
+pub const SRAM_INIT_ADDR: u32 = 0x0e000000;
+/// Size of the SRAM, mirrored over the whole region
+pub const SRAM_SIZE: u32 = 0x8000;
+
+/// sram::Sram
+///
+/// Battery backed SRAM of the game pak. The chip is connected through an 8 bits data bus: wider
+/// reads return the addressed byte repeated over the whole bus, while wider writes only store the
+/// byte of the bus lane corresponding to the address. The chip is mirrored every 32KB.
+pub struct Sram {
+    pub memory: Memory,
+}
+
+impl Sram {
+    pub fn new() -> Self {
+        Self {
+            memory: Memory::new(SRAM_INIT_ADDR, SRAM_SIZE, false, String::from("SRAM")),
+        }
+    }
+
+    /// Sram::read
+    ///
+    /// @param address [u32]: address of the access
+    /// @return [u32]: addressed byte, repeated on the 4 lanes of the bus
+    pub fn read(&self, address: u32) -> u32 {
+        self.memory.read_byte(Self::mirrored_address(address)) * 0x01010101
+    }
+
+    /// Sram::write
+    ///
+    /// @param address [u32]: address of the access
+    /// @param data [u32]: data on the bus
+    /// @param _mas [TransferSize]: size of the transfer, not relevant on an 8 bits bus
+    pub fn write(&mut self, address: u32, data: u32, _mas: TransferSize) {
+        self.memory.write8(
+            Self::mirrored_address(address),
+            (data >> ((address & 3) * 8)) as u8,
+        );
+    }
+
+    /// Sram::mirrored_address
+    ///
+    /// @param address [u32]: address of the access
+    /// @return [u32]: address in the first copy of the chip
+    fn mirrored_address(address: u32) -> u32 {
+        SRAM_INIT_ADDR | (address & (SRAM_SIZE - 1))
+    }
+}
+
+#[cfg(test)]
+mod test_sram {
+
+    use crate::backup::sram::Sram;
+    use crate::bus::TransferSize;
+
+    #[test]
+    fn test_sram_bus() {
+        let mut sram = Sram::new();
+
+        // Only the byte of the addressed lane is stored
+        sram.write(0x0e000001, 0xaabbccdd, TransferSize::WORD);
+        sram.write(0x0e000002, 0x11221122, TransferSize::HALFWORD);
+        assert_eq!(sram.memory.read_word(0x0e000000), 0x0022cc00);
+
+        // Reads repeat the addressed byte
+        assert_eq!(sram.read(0x0e000001), 0xcccccccc);
+        assert_eq!(sram.read(0x0e000002), 0x22222222);
+        assert_eq!(sram.read(0x0e000003), 0);
+        assert_eq!(sram.read(0x0e008001), 0xcccccccc);
+    }
+}
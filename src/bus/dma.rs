@@ -0,0 +1,221 @@
+use crate::bus::TransferSize;
+use crate::common::BitOperation;
+use crate::io::registers::{IoRegisters, IF};
+
+/// Address of the registers of each channel: source (+0), destination (+4), word count (+8) and
+/// control (+10)
+pub const DMA_BASE: [u32; 4] = [0x040000b0, 0x040000bc, 0x040000c8, 0x040000d4];
+
+/// dma::DmaTiming
+///
+/// Event starting a transfer, from bits 12-13 of DMAxCNT_H.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DmaTiming {
+    Immediate,
+    VBlank,
+    HBlank,
+    Special,
+}
+
+/// dma::DmaChannel
+///
+/// Internal registers of a channel, loaded from the I/O registers when the channel is enabled.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct DmaChannel {
+    pub source: u32,
+    pub destination: u32,
+    pub count: u32,
+    enabled: bool,
+}
+
+/// dma::Dma
+///
+/// State of the 4 DMA channels. The transfers themselves are performed by the bus, which owns the
+/// memories.
+pub struct Dma {
+    pub channels: [DmaChannel; 4],
+}
+
+impl Dma {
+    pub fn new() -> Self {
+        Self {
+            channels: [DmaChannel::default(); 4],
+        }
+    }
+
+    /// Dma::control
+    ///
+    /// @param channel [usize]: index of the channel
+    /// @param io [&IoRegisters]: I/O registers
+    /// @return [u32]: value of DMAxCNT_H
+    pub fn control(channel: usize, io: &IoRegisters) -> u32 {
+        io.get(DMA_BASE[channel] + 10)
+    }
+
+    /// Dma::timing
+    ///
+    /// @param control [u32]: value of DMAxCNT_H
+    /// @return [DmaTiming]: event starting the transfers of the channel
+    pub fn timing(control: u32) -> DmaTiming {
+        match control.get_range(13, 12) {
+            0 => DmaTiming::Immediate,
+            1 => DmaTiming::VBlank,
+            2 => DmaTiming::HBlank,
+            _ => DmaTiming::Special,
+        }
+    }
+
+    /// Dma::transfer_size
+    ///
+    /// @param control [u32]: value of DMAxCNT_H
+    /// @return [TransferSize]: size of each unit of the transfer
+    pub fn transfer_size(control: u32) -> TransferSize {
+        if control.is_bit_set(10) {
+            TransferSize::WORD
+        } else {
+            TransferSize::HALFWORD
+        }
+    }
+
+    /// Dma::control_written
+    ///
+    /// Handle a write to DMAxCNT_H. When the channel gets enabled, its internal registers are
+    /// loaded.
+    ///
+    /// @param channel [usize]: index of the channel
+    /// @param io [&IoRegisters]: I/O registers
+    /// @return [bool]: true if the transfer is to be started immediately
+    pub fn control_written(&mut self, channel: usize, io: &IoRegisters) -> bool {
+        let control = Self::control(channel, io);
+        let was_enabled = self.channels[channel].enabled;
+        self.channels[channel].enabled = control.is_bit_set(15);
+
+        if !self.channels[channel].enabled || was_enabled {
+            return false;
+        }
+
+        self.channels[channel].source = io.get_word(DMA_BASE[channel]);
+        self.channels[channel].destination = io.get_word(DMA_BASE[channel] + 4);
+        self.channels[channel].count = Self::word_count(channel, io);
+        Self::timing(control) == DmaTiming::Immediate
+    }
+
+    /// Dma::is_triggered
+    ///
+    /// @param channel [usize]: index of the channel
+    /// @param timing [DmaTiming]: event which occurred
+    /// @param io [&IoRegisters]: I/O registers
+    /// @return [bool]: true if the channel is waiting for the event
+    pub fn is_triggered(&self, channel: usize, timing: DmaTiming, io: &IoRegisters) -> bool {
+        self.channels[channel].enabled && Self::timing(Self::control(channel, io)) == timing
+    }
+
+    /// Dma::next_address
+    ///
+    /// @param address [u32]: current address
+    /// @param address_control [u32]: 0 increment, 1 decrement, 2 fixed, 3 increment (and reload
+    /// for the destination)
+    /// @param mas [TransferSize]: size of each unit of the transfer
+    /// @return [u32]: address of the next unit
+    pub fn next_address(address: u32, address_control: u32, mas: TransferSize) -> u32 {
+        let size = if mas == TransferSize::WORD { 4 } else { 2 };
+        match address_control {
+            1 => address.wrapping_sub(size),
+            2 => address,
+            _ => address.wrapping_add(size),
+        }
+    }
+
+    /// Dma::finish
+    ///
+    /// Update a channel at the end of a transfer: repeating channels are reloaded, while the
+    /// others are disabled. The interrupt request is raised if enabled.
+    ///
+    /// @param channel [usize]: index of the channel
+    /// @param source [u32]: source address after the transfer
+    /// @param destination [u32]: destination address after the transfer
+    /// @param io [&mut IoRegisters]: I/O registers
+    pub fn finish(&mut self, channel: usize, source: u32, destination: u32, io: &mut IoRegisters) {
+        let control = Self::control(channel, io);
+
+        if control.is_bit_set(14) {
+            io.set(IF, io.get(IF).set_bit(8 + channel as u32));
+        }
+
+        self.channels[channel].source = source;
+        self.channels[channel].destination = destination;
+
+        if control.is_bit_set(9) && Self::timing(control) != DmaTiming::Immediate {
+            self.channels[channel].count = Self::word_count(channel, io);
+            if control.get_range(6, 5) == 3 {
+                self.channels[channel].destination = io.get_word(DMA_BASE[channel] + 4);
+            }
+        } else {
+            self.channels[channel].enabled = false;
+            io.set(DMA_BASE[channel] + 10, control.clear_bit(15));
+        }
+    }
+
+    /// Dma::word_count
+    ///
+    /// @param channel [usize]: index of the channel
+    /// @param io [&IoRegisters]: I/O registers
+    /// @return [u32]: number of units to transfer, where 0 stands for the maximum
+    fn word_count(channel: usize, io: &IoRegisters) -> u32 {
+        match io.get(DMA_BASE[channel] + 8) {
+            0 if channel == 3 => 0x10000,
+            0 => 0x4000,
+            count => count,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_dma {
+
+    use crate::bus::dma::{Dma, DmaTiming, DMA_BASE};
+    use crate::bus::TransferSize;
+    use crate::io::registers::{IoRegisters, IF};
+
+    #[test]
+    fn test_next_address() {
+        let word = TransferSize::WORD;
+        assert_eq!(Dma::next_address(0x100, 0, word), 0x104);
+        assert_eq!(Dma::next_address(0x100, 1, TransferSize::HALFWORD), 0xfe);
+        assert_eq!(Dma::next_address(0x100, 2, word), 0x100);
+        assert_eq!(Dma::next_address(0x100, 3, word), 0x104);
+    }
+
+    #[test]
+    fn test_channel_control() {
+        let mut io = IoRegisters::new();
+        let mut dma = Dma::new();
+        let base = DMA_BASE[3];
+
+        io.write(base, 0x08000000, TransferSize::WORD);
+        io.write(base + 4, 0x02000000, TransferSize::WORD);
+        io.write(base + 8, 0x8400_0010, TransferSize::WORD);
+        assert!(dma.control_written(3, &io));
+        assert_eq!(dma.channels[3].source, 0x08000000);
+        assert_eq!(dma.channels[3].destination, 0x02000000);
+        assert_eq!(dma.channels[3].count, 0x10);
+        assert_eq!(Dma::transfer_size(Dma::control(3, &io)), TransferSize::WORD);
+
+        // Immediate transfers are not repeated
+        dma.finish(3, 0x08000040, 0x02000040, &mut io);
+        assert_eq!(io.get(base + 10), 0x0400);
+        assert!(!dma.is_triggered(3, DmaTiming::Immediate, &io));
+
+        // Repeated vblank transfer with destination reload and interrupt request
+        io.write(base + 8, 0x0000, TransferSize::HALFWORD);
+        io.write(base + 10, 0xd260_d260, TransferSize::HALFWORD);
+        assert!(!dma.control_written(3, &io));
+        assert_eq!(dma.channels[3].count, 0x10000);
+        assert!(dma.is_triggered(3, DmaTiming::VBlank, &io));
+        dma.finish(3, 0x08000100, 0x02000100, &mut io);
+        assert!(dma.is_triggered(3, DmaTiming::VBlank, &io));
+        assert_eq!(dma.channels[3].source, 0x08000100);
+        assert_eq!(dma.channels[3].destination, 0x02000000);
+        assert_eq!(io.get(IF), 1 << 11);
+    }
+}
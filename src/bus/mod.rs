@@ -1,7 +1,34 @@
+pub mod dma;
+pub mod open_bus;
+pub mod prefetch;
+pub mod waitstates;
+
+use crate::apu::audio_capture::AudioCapture;
+use crate::apu::audio_output::AudioOutput;
+use crate::apu::{Apu, FIFO_A, FIFO_B};
 use crate::arm7_tdmi;
+use crate::backup::save_file::SaveFile;
+use crate::backup::{Backup, BackupType};
+use crate::bus::dma::{Dma, DmaTiming};
+use crate::bus::open_bus::OpenBus;
+use crate::bus::prefetch::Prefetch;
+use crate::bus::waitstates::WaitControl;
+use crate::cartridge::game_database::GameSettings;
+use crate::cartridge::gpio::Gpio;
+use crate::cartridge::header::CartridgeHeader;
+use crate::cartridge::rtc::{Rtc, RtcClock};
+use crate::cartridge::sensors::{
+    GyroSensor, Rumble, SolarSensor, TiltSensor, GYRO_CENTER, GYRO_RANGE, TILT_CENTER, TILT_RANGE,
+};
+use crate::common::BitOperation;
 use crate::gpu;
 use crate::io::keypad;
+use crate::io::registers::{IoHook, IoRegisters, DISPSTAT, IE, IF, VCOUNT, WAITCNT};
+use crate::io::sensor_input::SensorChanges;
+use crate::io::timers::Timers;
 use crate::memory;
+use std::io;
+use std::path::{Path, PathBuf};
 
 /// bus::TransferSize
 ///
@@ -41,6 +68,9 @@ pub enum BusSignal {
     LOW = 0,
 }
 
+/// Value of the last opcode fetched from the bios once the boot sequence is over
+pub const BIOS_OPCODE_AFTER_STARTUP: u32 = 0xe129f000;
+
 /// bus::MemoryRequest
 ///
 /// structure to represent a request towards the bus
@@ -70,14 +100,35 @@ pub struct Bus {
     pub cpu: arm7_tdmi::ARM7TDMI,
     pub gpu: gpu::Gpu,
     pub keypad: keypad::Keypad,
+    pub io: IoRegisters,
     pub gamepak: memory::Memory,
-    pub gamepak_sram: memory::Memory,
+    pub gpio: Gpio,
+    pub tilt: Option<TiltSensor>,
+    pub backup: Backup,
+    pub save_file: Option<SaveFile>,
     pub ewram: memory::Memory,
     pub iwram: memory::Memory,
     pub bios: memory::Memory,
+    pub wait_control: WaitControl,
+    pub prefetch: Prefetch,
+    pub open_bus: OpenBus,
+    pub dma: Dma,
+    pub timers: Timers,
+    pub apu: Apu,
+    pub audio: Option<AudioOutput>,
+    audio_capture: Option<AudioCapture>,
+    idle_loop: Option<u32>,
+    idle: bool,
+    headless: bool,
+    bios_last_opcode: u32,
     next_cpu_response: MemoryResponse,
     next_transaction: BusCycle,
+    wait_cycles: u32,
+    gamepak_busy: bool,
     step_counter: u64,
+    running: bool,
+    dma_cycles: u32,
+    last_dispstat: u32,
 }
 
 impl Bus {
@@ -86,26 +137,327 @@ impl Bus {
             cpu: arm7_tdmi::ARM7TDMI::new(),
             gpu: gpu::Gpu::new(),
             keypad: keypad::Keypad::new(),
-            gamepak: memory::Memory::new(0x08000000, 0x06000000, true, String::from("GAMEPAK")),
-            gamepak_sram: memory::Memory::new(0x0e000000, 0x10000, false, String::from("GAMEPAK")),
+            io: IoRegisters::new(),
+            gamepak: memory::Memory::new(0x08000000, 0, true, String::from("GAMEPAK")),
+            gpio: Gpio::new(),
+            tilt: None,
+            backup: Backup::new(BackupType::Sram),
+            save_file: None,
             ewram: memory::Memory::new(0x02000000, 0x00040000, false, String::from("EWRAM")),
             iwram: memory::Memory::new(0x03000000, 0x00008000, false, String::from("IWRAM")),
             bios: memory::Memory::new(0x00000000, 0x00004000, true, String::from("BIOS")),
+            wait_control: WaitControl::new(),
+            prefetch: Prefetch::new(),
+            open_bus: OpenBus::new(),
+            dma: Dma::new(),
+            timers: Timers::new(),
+            apu: Apu::new(),
+            audio: None,
+            audio_capture: None,
+            idle_loop: None,
+            idle: false,
+            headless: false,
+            bios_last_opcode: BIOS_OPCODE_AFTER_STARTUP,
             next_cpu_response: MemoryResponse {
                 data: arm7_tdmi::NOP,
                 n_wait: BusSignal::HIGH,
             },
             next_transaction: BusCycle::SEQUENTIAL,
+            wait_cycles: 0,
+            gamepak_busy: false,
             step_counter: 0,
+            running: true,
+            dma_cycles: 0,
+            last_dispstat: 0,
+        }
+    }
+
+    /// Bus::reset
+    ///
+    /// Hard reset of the system: the cpu, the I/O registers and the volatile memories go back to
+    /// their post-boot state, while the game pak and the bios keep their content.
+    ///
+    /// @param ram_seed [Option<u64>]: when provided, the RAMs are filled with pseudo-random
+    /// content generated from the seed instead of being cleared
+    pub fn reset(&mut self, ram_seed: Option<u64>) {
+        self.cpu.reset();
+        self.gpu.reset(ram_seed);
+        self.io.reset();
+        self.ewram.reset(ram_seed);
+        self.iwram.reset(ram_seed);
+        self.wait_control.set_waitcnt(self.io.get(WAITCNT));
+        self.prefetch = Prefetch::new();
+        self.open_bus = OpenBus::new();
+        self.dma = Dma::new();
+        self.timers = Timers::new();
+        self.apu = Apu::new();
+        self.bios_last_opcode = BIOS_OPCODE_AFTER_STARTUP;
+        self.next_cpu_response = MemoryResponse {
+            data: arm7_tdmi::NOP,
+            n_wait: BusSignal::HIGH,
+        };
+        self.next_transaction = BusCycle::SEQUENTIAL;
+        self.wait_cycles = 0;
+        self.gamepak_busy = false;
+        self.step_counter = 0;
+        self.dma_cycles = 0;
+        self.last_dispstat = 0;
+    }
+
+    /// Bus::is_running
+    ///
+    /// @return [bool]: false once the user asked to close the emulator
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// Bus::cartridge_header
+    ///
+    /// @return [Option<CartridgeHeader>]: header of the loaded rom, if it is large enough
+    pub fn cartridge_header(&self) -> Option<CartridgeHeader> {
+        CartridgeHeader::parse(self.gamepak.as_bytes())
+    }
+
+    /// Bus::set_headless
+    ///
+    /// Run without a window: the frames are not displayed and the keyboard and game controller
+    /// events are not polled, so that SDL is never initialized by the emulation.
+    ///
+    /// @param headless [bool]: true to run without a window
+    pub fn set_headless(&mut self, headless: bool) {
+        self.headless = headless;
+        self.gpu.headless = headless;
+    }
+
+    /// Bus::configure
+    ///
+    /// Set up the hardware of the game pak which cannot be detected from the rom. This must be
+    /// done before booting and loading the save.
+    ///
+    /// @param settings [GameSettings]: settings of the game
+    /// @param rtc_clock [RtcClock]: time source of the real time clock, if the game has one
+    pub fn configure(&mut self, settings: GameSettings, rtc_clock: RtcClock) {
+        self.gpio = Gpio::new();
+        if settings.rtc {
+            self.gpio.rtc = Some(Rtc::new(rtc_clock));
+        }
+        self.gpio.solar = settings.solar.then(SolarSensor::new);
+        self.gpio.gyro = settings.gyro.then(GyroSensor::new);
+        self.gpio.rumble = settings.rumble.then(Rumble::new);
+        self.tilt = settings.tilt.then(TiltSensor::new);
+        self.idle_loop = settings.idle_loop;
+        if let Some(save_type) = settings.save_type {
+            self.select_backup(save_type);
+        }
+    }
+
+    /// Bus::set_light_level
+    ///
+    /// @param level [u8]: light received by the solar sensor, from 0 (dark) to 255
+    pub fn set_light_level(&mut self, level: u8) {
+        if let Some(solar) = self.gpio.solar.as_mut() {
+            solar.light_level = level;
+        }
+    }
+
+    /// Bus::light_level
+    ///
+    /// @return [Option<u8>]: light received by the solar sensor, if the game pak has one
+    pub fn light_level(&self) -> Option<u8> {
+        self.gpio.solar.as_ref().map(|solar| solar.light_level)
+    }
+
+    /// Bus::set_tilt
+    ///
+    /// @param x [u16]: raw value of the x axis of the tilt sensor, 0x3a0 when horizontal
+    /// @param y [u16]: raw value of the y axis of the tilt sensor, 0x3a0 when horizontal
+    pub fn set_tilt(&mut self, x: u16, y: u16) {
+        if let Some(tilt) = self.tilt.as_mut() {
+            tilt.x = x;
+            tilt.y = y;
+        }
+    }
+
+    /// Bus::tilt
+    ///
+    /// @return [Option<(u16, u16)>]: raw values of the tilt sensor, if the game pak has one
+    #[allow(dead_code)] // the keypad only sends the changes of the stick
+    pub fn tilt(&self) -> Option<(u16, u16)> {
+        self.tilt.as_ref().map(|tilt| (tilt.x, tilt.y))
+    }
+
+    /// Bus::set_rotation
+    ///
+    /// @param rotation [u16]: raw value of the gyro sensor, 0x6c0 when not rotating
+    pub fn set_rotation(&mut self, rotation: u16) {
+        if let Some(gyro) = self.gpio.gyro.as_mut() {
+            gyro.rotation = rotation;
+        }
+    }
+
+    /// Bus::is_rumbling
+    ///
+    /// @return [bool]: true if the rumble motor of the game pak is active
+    pub fn is_rumbling(&self) -> bool {
+        self.gpio
+            .rumble
+            .as_ref()
+            .is_some_and(|rumble| rumble.active)
+    }
+
+    /// Bus::apply_sensor_changes
+    ///
+    /// Update the sensors of the game pak with the controls used by the user.
+    ///
+    /// @param changes [SensorChanges]: changes requested through the keypad
+    fn apply_sensor_changes(&mut self, changes: SensorChanges) {
+        if let Some(level) = self.light_level() {
+            let level = (level as i32 + changes.light_delta * 4).clamp(0, 255);
+            self.set_light_level(level as u8);
+        }
+
+        let scale = |center: u16, range: u16, value: i16| {
+            (center as i32 + value as i32 * range as i32 / i16::MAX as i32) as u16
+        };
+        if let Some((x, y)) = changes.tilt {
+            self.set_tilt(
+                scale(TILT_CENTER, TILT_RANGE, x),
+                scale(TILT_CENTER, TILT_RANGE, y),
+            );
+        }
+        if let Some(rotation) = changes.rotation {
+            self.set_rotation(scale(GYRO_CENTER, GYRO_RANGE, rotation));
+        }
+    }
+
+    /// Bus::select_backup
+    ///
+    /// Replace the backup memory of the game pak, which must be done before loading the save.
+    ///
+    /// @param backup_type [BackupType]: kind of backup memory of the game
+    pub fn select_backup(&mut self, backup_type: BackupType) {
+        self.backup = Backup::new(backup_type);
+    }
+
+    /// Bus::load_save
+    ///
+    /// Associate a save file to the backup memory of the game pak, and load its content if the
+    /// file exists.
+    ///
+    /// @param path [PathBuf]: path of the save file
+    pub fn load_save(&mut self, path: PathBuf) {
+        let save_file = SaveFile::new(path);
+        if let Some(size) = save_file.size() {
+            self.backup.set_save_size(size);
+        }
+        match save_file.load(self.backup.data_mut()) {
+            Ok(true) => println!("Loaded save file {}", save_file.path().display()),
+            Ok(false) => {}
+            Err(e) => println!(
+                "Unable to load save file {}: {}",
+                save_file.path().display(),
+                e
+            ),
+        }
+        self.save_file = Some(save_file);
+    }
+
+    /// Bus::flush_save
+    ///
+    /// Write the backup memory of the game pak to the save file, if it was modified.
+    pub fn flush_save(&mut self) {
+        if let Some(save_file) = self.save_file.as_mut() {
+            if let Err(e) = save_file.flush(self.backup.data()) {
+                println!(
+                    "Unable to write save file {}: {}",
+                    save_file.path().display(),
+                    e
+                );
+            }
+        }
+    }
+
+    /// Bus::start_audio_capture
+    ///
+    /// Record the sound to WAV files, until `stop_audio_capture` is called. This doesn't need an
+    /// audio device.
+    ///
+    /// @param path [&Path]: path of the file of the mixed output
+    /// @param per_channel [bool]: true to also record each channel to its own file
+    /// @return [io::Result<()>]: error creating the files, if any
+    pub fn start_audio_capture(&mut self, path: &Path, per_channel: bool) -> io::Result<()> {
+        self.stop_audio_capture()?;
+        let capture = AudioCapture::create(path, per_channel, Apu::sample_rate(&self.io))?;
+        self.audio_capture = Some(capture);
+        Ok(())
+    }
+
+    /// Bus::stop_audio_capture
+    ///
+    /// @return [io::Result<()>]: error completing the files, if any
+    pub fn stop_audio_capture(&mut self) -> io::Result<()> {
+        match self.audio_capture.take() {
+            Some(capture) => capture.finish(),
+            None => Ok(()),
         }
     }
 
     pub fn step(&mut self) {
-        let cpu_request = self.cpu.step(self.next_cpu_response);
-        self.gpu.step();
+        // The cpu is not run while the game waits for an interrupt in its idle loop
+        if self.idle && self.io.get(IE) & self.io.get(IF) != 0 {
+            self.idle = false;
+        }
+        let cpu_request = (!self.idle).then(|| self.cpu.step(self.next_cpu_response));
+        self.gpu.step(&mut self.io);
+        let overflows = self.timers.step(&mut self.io);
+        if overflows & 0b11 != 0 {
+            self.refill_sound_fifos(overflows);
+        }
+        if let Some(sample) = self.apu.step(&mut self.io) {
+            self.output_sample(sample);
+        }
+        self.trigger_display_dma();
 
         if self.step_counter % 279620 == 0 {
-            self.keypad.step();
+            if !self.headless && self.keypad.step(&mut self.io) {
+                self.running = false;
+            }
+            for command in self.keypad.take_audio_commands() {
+                if let Some(audio) = self.audio.as_mut() {
+                    audio.apply(command);
+                }
+            }
+            let changes = self.keypad.take_sensor_changes();
+            self.apply_sensor_changes(changes);
+            self.keypad.set_rumble(self.is_rumbling());
+            if self.gpio.poll_irq() {
+                self.io.set(IF, self.io.get(IF).set_bit(13));
+            }
+        }
+
+        if self.save_file.as_mut().is_some_and(|save| save.step()) {
+            self.flush_save();
+        }
+
+        self.step_counter += 1;
+
+        let Some(cpu_request) = cpu_request else {
+            return;
+        };
+
+        // The cpu is stalled by an access requiring waitstates: the request it sent is a
+        // repetition of the fetch and it must be ignored. When the last waitstate is over, the
+        // response which was computed at the beginning of the access is made valid.
+        if self.wait_cycles > 0 {
+            self.wait_cycles += std::mem::take(&mut self.dma_cycles);
+            if !self.gamepak_busy {
+                self.prefetch.step(&self.wait_control);
+            }
+            self.wait_cycles -= 1;
+            if self.wait_cycles == 0 {
+                self.next_cpu_response.n_wait = BusSignal::HIGH;
+            }
+            return;
         }
 
         if self.next_transaction != BusCycle::INTERNAL {
@@ -114,10 +466,49 @@ impl Bus {
             } else {
                 self.next_cpu_response = self.write(cpu_request);
             }
+
+            // Sequential accesses are signaled by the cpu together with the previous request
+            let sequential = self.next_transaction == BusCycle::SEQUENTIAL;
+
+            // A branch to the idle loop skips ahead to the next interrupt
+            if cpu_request.n_opc == BusSignal::LOW
+                && !sequential
+                && Some(cpu_request.address) == self.idle_loop
+            {
+                self.idle = true;
+            }
+            self.gamepak_busy =
+                cpu_request.address >= 0x08000000 && cpu_request.address <= 0x0dffffff;
+
+            let access_cycles = if !self.gamepak_busy {
+                self.prefetch.step(&self.wait_control);
+                self.wait_control
+                    .access_cycles(cpu_request.address, cpu_request.mas, sequential)
+            } else if self.wait_control.is_prefetch_enabled() {
+                self.prefetch.access(
+                    cpu_request.address,
+                    cpu_request.mas,
+                    cpu_request.n_opc == BusSignal::LOW && cpu_request.nr_w == BusSignal::LOW,
+                    sequential,
+                    &self.wait_control,
+                )
+            } else {
+                self.prefetch.discard();
+                self.wait_control
+                    .access_cycles(cpu_request.address, cpu_request.mas, sequential)
+            };
+
+            // Cycles of the DMA transfers which occurred since the last access
+            let access_cycles = access_cycles + std::mem::take(&mut self.dma_cycles);
+            if access_cycles > 1 {
+                self.wait_cycles = access_cycles - 1;
+                self.next_cpu_response.n_wait = BusSignal::LOW;
+            }
+        } else {
+            // Internal cycle: the game pak bus is free to be used by the prefetch buffer
+            self.prefetch.step(&self.wait_control);
         }
         self.next_transaction = cpu_request.bus_cycle;
-
-        self.step_counter += 1;
     }
 
     fn read(&mut self, req: MemoryRequest) -> MemoryResponse {
@@ -126,68 +517,568 @@ impl Bus {
             n_wait: BusSignal::HIGH,
         };
 
-        if req.address <= 0x00003ffff {
-            rsp.data = self.bios.read(req.address, req.mas)
-        } else if req.address >= 0x02000000 && req.address <= 0x02ffffff {
-            rsp.data = self.ewram.read(req.address & 0x0203ffff, req.mas)
-        } else if req.address >= 0x03000000 && req.address <= 0x03ffffff {
-            rsp.data = self.iwram.read(req.address & 0x03007fff, req.mas)
-        } else if req.address >= 0x04000000 && req.address <= 0x04000058 {
-            rsp.data = self.gpu.read(req.address, req.mas);
-        } else if req.address >= 0x04000130 && req.address <= 0x04000133 {
-            rsp.data = self.keypad.read(req.address, req.mas);
-        } else if req.address >= 0x05000000 && req.address <= 0x05000400 {
-            rsp.data = self.gpu.read(req.address, req.mas);
-        } else if req.address >= 0x06000000 && req.address <= 0x06018000 {
-            rsp.data = self.gpu.read(req.address, req.mas);
-        } else if req.address >= 0x07000000 && req.address <= 0x07000400 {
-            rsp.data = self.gpu.read(req.address, req.mas);
-        } else if req.address >= 0x08000000 && req.address <= 0x0dffffff {
-            rsp.data = self.gamepak.read(req.address, req.mas)
-        } else if req.address >= 0x0e000000 {
-            rsp.data = self
-                .gamepak_sram
-                .read(req.address & 0xffff | 0x0e000000, req.mas)
-        } else {
-            todo!("reading from {:#08x}", req.address);
+        let address = Self::mirrored_address(req.address);
+
+        rsp.data = match address {
+            _ if self.is_eeprom_address(req.address) => match &mut self.backup {
+                Backup::Eeprom(eeprom) => eeprom.read(),
+                _ => unreachable!(),
+            },
+            0x00000000..=0x00003fff => self.read_bios(req),
+            0x02000000..=0x02ffffff => self.ewram.read(address, req.mas),
+            0x03000000..=0x03ffffff => self.iwram.read(address, req.mas),
+            0x04000000..=0x040003ff => {
+                let open_bus = self.open_bus.value(req.t_bit == BusSignal::HIGH);
+                self.io.read(address, open_bus)
+            }
+            0x05000000..=0x07ffffff => self.gpu.read(address, req.mas),
+            0x08000000..=0x0dffffff => self.read_gamepak(address, req.mas),
+            _ if self.tilt.is_some() && TiltSensor::is_tilt_address(address) => {
+                self.tilt.as_ref().map_or(0, |tilt| tilt.read(address))
+            }
+            0x0e000000..=0x0fffffff => self.backup.read(address),
+            _ => self.open_bus.value(req.t_bit == BusSignal::HIGH),
+        };
+
+        if req.n_opc == BusSignal::LOW {
+            self.open_bus.record_fetch(req.address, rsp.data);
         }
 
         return rsp;
     }
 
+    /// Bus::mirrored_address
+    ///
+    /// Most of the regions are smaller than the space they are mapped into, and they are mirrored
+    /// over the whole space. This function maps an address to the corresponding address in the
+    /// first copy of its region:
+    ///
+    /// Region      Size    Mirrored over
+    /// -------------------------------------------------------
+    /// EWRAM       256KB   0x02000000 - 0x02ffffff
+    /// IWRAM       32KB    0x03000000 - 0x03ffffff
+    /// PRAM        1KB     0x05000000 - 0x05ffffff
+    /// VRAM        96KB    0x06000000 - 0x06ffffff (*)
+    /// OAM         1KB     0x07000000 - 0x07ffffff
+    /// ROM         32MB    0x08000000 - 0x0dffffff (WS0, WS1, WS2, see `read_gamepak`)
+    /// Backup      64KB    0x0e000000 - 0x0fffffff (SRAM is mirrored every 32KB)
+    /// -------------------------------------------------------
+    ///
+    /// (*) VRAM is mirrored every 128KB, with 0x06018000 - 0x0601ffff mapped to the OBJ tiles at
+    /// 0x06010000 - 0x06017fff.
+    ///
+    /// @param address [u32]: address of the access
+    /// @return [u32]: address in the first copy of the region
+    fn mirrored_address(address: u32) -> u32 {
+        match address >> 24 {
+            0x02 => 0x02000000 | address.get_range(17, 0),
+            0x03 => 0x03000000 | address.get_range(14, 0),
+            0x05 => 0x05000000 | address.get_range(9, 0),
+            0x06 => {
+                let offset = address.get_range(16, 0);
+                0x06000000
+                    | if offset >= 0x18000 {
+                        offset - 0x8000
+                    } else {
+                        offset
+                    }
+            }
+            0x07 => 0x07000000 | address.get_range(9, 0),
+            0x08..=0x0d => 0x08000000 | address.get_range(24, 0),
+            0x0e | 0x0f => 0x0e000000 | address.get_range(15, 0),
+            _ => address,
+        }
+    }
+
+    /// Bus::read_gamepak
+    ///
+    /// The storage of the game pak ROM is as large as the loaded image, and it is shared by the
+    /// three waitstate windows. Images smaller than 32MB are not mirrored: a game pak does not
+    /// repeat its content, and beyond the end of the image the bus still holds the lower 16 bits
+    /// of the address of each halfword, sent by the gba in the address phase of the access. The
+    /// registers of the gpio port can be mapped over the ROM.
+    ///
+    /// @param address [u32]: address in the first waitstate window
+    /// @param mas [TransferSize]: size of the transfer
+    /// @return [u32]: data read
+    fn read_gamepak(&self, address: u32, mas: TransferSize) -> u32 {
+        let rom_data = if address - 0x08000000 < self.gamepak.size() {
+            self.gamepak.read(address, mas)
+        } else {
+            let halfword_address = (address & !3) >> 1;
+            (halfword_address & 0xffff) | ((halfword_address.wrapping_add(1) & 0xffff) << 16)
+        };
+
+        if Gpio::is_gpio_address(address) {
+            return self.gpio.read(address, rom_data);
+        }
+        rom_data
+    }
+
+    /// Bus::read_bios
+    ///
+    /// The bios can be read only while the program counter is inside of it. Otherwise, the read
+    /// returns the last opcode which was fetched from the bios.
+    ///
+    /// @param req [MemoryRequest]: read request towards the bios
+    /// @return [u32]: data read
+    fn read_bios(&mut self, req: MemoryRequest) -> u32 {
+        if req.n_opc == BusSignal::LOW {
+            self.bios_last_opcode = self.bios.read(req.address, req.mas);
+            self.bios_last_opcode
+        } else if self.open_bus.last_fetch_address() <= 0x00003fff {
+            self.bios.read(req.address, req.mas)
+        } else {
+            self.bios_last_opcode
+        }
+    }
+
     fn write(&mut self, req: MemoryRequest) -> MemoryResponse {
         let rsp = MemoryResponse {
             data: 0,
             n_wait: BusSignal::HIGH,
         };
 
-        if req.address >= 0x08000000 && req.address <= 0x0dffffff {
-            self.gamepak.write(req.address, req.data, req.mas)
-        } else if req.address <= 0x00003ffff {
-            self.bios.write(req.address, req.data, req.mas)
-        } else if req.address >= 0x02000000 && req.address <= 0x02ffffff {
-            self.ewram
-                .write(req.address & 0x0203ffff, req.data, req.mas)
-        } else if req.address >= 0x03000000 && req.address <= 0x03ffffff {
-            self.iwram
-                .write(req.address & 0x03007fff, req.data, req.mas)
-        } else if req.address >= 0x06000000 && req.address <= 0x06018000 {
-            self.gpu.write(req.address, req.data, req.mas);
-        } else if req.address >= 0x05000000 && req.address <= 0x05000400 {
-            self.gpu.write(req.address, req.data, req.mas);
-        } else if req.address >= 0x07000000 && req.address <= 0x07000400 {
-            self.gpu.write(req.address, req.data, req.mas);
-        } else if req.address >= 0x04000000 && req.address <= 0x04000058 {
-            self.gpu.write(req.address, req.data, req.mas);
-        } else if req.address >= 0x04000130 && req.address <= 0x04000133 {
-            self.keypad.write(req.address, req.data, req.mas);
-        } else if req.address >= 0x0e000000 {
-            self.gamepak_sram
-                .write(req.address & 0xffff | 0x0e000000, req.data, req.mas);
-        } else {
-            todo!("writing to {:#08x}", req.address);
+        let address = Self::mirrored_address(req.address);
+
+        match address {
+            _ if self.is_eeprom_address(req.address) => {
+                if let Backup::Eeprom(eeprom) = &mut self.backup {
+                    eeprom.write(req.data);
+                }
+                if let Some(save_file) = self.save_file.as_mut() {
+                    save_file.mark_dirty();
+                }
+            }
+            0x00000000..=0x00003fff => self.bios.write(address, req.data, req.mas),
+            0x02000000..=0x02ffffff => self.ewram.write(address, req.data, req.mas),
+            0x03000000..=0x03ffffff => self.iwram.write(address, req.data, req.mas),
+            0x04000000..=0x040003ff => self.write_io(address, req.data, req.mas),
+            0x05000000..=0x07ffffff => self.gpu.write(address, req.data, req.mas, &self.io),
+            _ if Gpio::is_gpio_address(address) => self.gpio.write(address, req.data, req.mas),
+            0x08000000..=0x0dffffff => self.gamepak.write(address, req.data, req.mas),
+            _ if self.tilt.is_some() && TiltSensor::is_tilt_address(address) => {
+                if let Some(tilt) = self.tilt.as_mut() {
+                    tilt.write(address, req.data);
+                }
+            }
+            0x0e000000..=0x0fffffff => {
+                self.backup.write(address, req.data, req.mas);
+                if let Some(save_file) = self.save_file.as_mut() {
+                    save_file.mark_dirty();
+                }
+            }
+            _ => {}
         }
 
         return rsp;
     }
+
+    /// Bus::write_io
+    ///
+    /// Write to the I/O registers, and handle the side effects of the write.
+    ///
+    /// @param address [u32]: address of the access
+    /// @param data [u32]: data on the bus
+    /// @param mas [TransferSize]: size of the access
+    fn write_io(&mut self, address: u32, data: u32, mas: TransferSize) {
+        for event in self.io.write(address, data, mas).into_iter().flatten() {
+            match event.hook {
+                IoHook::WaitControl => self.wait_control.set_waitcnt(self.io.get(WAITCNT)),
+                IoHook::InterruptAcknowledge => {
+                    let pending = self.io.get(IF) & !(event.data & event.lanes);
+                    self.io.set(IF, pending);
+                }
+                IoHook::DmaControl(channel) => {
+                    if self.dma.control_written(channel, &self.io) {
+                        self.run_dma(channel);
+                    }
+                }
+                IoHook::Sound => self
+                    .apu
+                    .write_register(&mut self.io, event.address, event.lanes),
+                IoHook::TimerReload(timer) => {
+                    self.timers
+                        .reload_written(timer, event.data, event.lanes, &mut self.io)
+                }
+                IoHook::TimerControl(timer) => self.timers.control_written(timer, &mut self.io),
+            }
+        }
+    }
+
+    /// Bus::is_eeprom_address
+    ///
+    /// The EEPROM is mapped at 0x0d000000 - 0x0dffffff on game paks up to 16MB, while on larger
+    /// ones it only takes the last 256 bytes of the region.
+    ///
+    /// @param address [u32]: address of the access
+    /// @return [bool]: true if the access is directed to the EEPROM
+    fn is_eeprom_address(&self, address: u32) -> bool {
+        matches!(self.backup, Backup::Eeprom(_))
+            && address >> 24 == 0x0d
+            && (self.gamepak.size() <= 0x01000000 || address >= 0x0dffff00)
+    }
+
+    /// Bus::trigger_display_dma
+    ///
+    /// Start the DMA channels waiting for the beginning of a vblank or of an hblank. HBlank
+    /// transfers do not occur during the vblank.
+    fn trigger_display_dma(&mut self) {
+        let dispstat = self.io.get(DISPSTAT);
+        let rising = dispstat & !self.last_dispstat;
+        self.last_dispstat = dispstat;
+
+        let mut timings = Vec::new();
+        if rising.is_bit_set(0) {
+            timings.push(DmaTiming::VBlank);
+        }
+        if rising.is_bit_set(1) && self.io.get(VCOUNT) < 160 {
+            timings.push(DmaTiming::HBlank);
+        }
+
+        for timing in timings {
+            for channel in 0..4 {
+                if self.dma.is_triggered(channel, timing, &self.io) {
+                    self.run_dma(channel);
+                }
+            }
+        }
+    }
+
+    /// Bus::output_sample
+    ///
+    /// Send a sample of the mixer to the audio device and to the recording, if any. A recording
+    /// which fails is stopped.
+    ///
+    /// @param sample [(i16, i16)]: left and right output of the mixer
+    fn output_sample(&mut self, sample: (i16, i16)) {
+        let sample_rate = Apu::sample_rate(&self.io);
+        if let Some(audio) = self.audio.as_mut() {
+            audio.push(sample, sample_rate);
+        }
+
+        if let Some(capture) = self.audio_capture.as_mut() {
+            let channels = self.apu.channel_samples();
+            if let Err(e) = capture.push(sample, channels, sample_rate) {
+                println!("Unable to record the audio: {}", e);
+                self.audio_capture = None;
+            }
+        }
+    }
+
+    /// Bus::refill_sound_fifos
+    ///
+    /// Play the next samples of the Direct Sound channels after an overflow of timer 0 or 1, and
+    /// start the DMA channels with the special timing which feed the FIFOs running low.
+    ///
+    /// @param overflows [u32]: bit i set if timer i overflowed
+    fn refill_sound_fifos(&mut self, overflows: u32) {
+        let requests = self.apu.timer_overflow(&self.io, overflows);
+
+        for (address, _) in [FIFO_A, FIFO_B]
+            .into_iter()
+            .zip(requests)
+            .filter(|(_, r)| *r)
+        {
+            for channel in 1..3 {
+                if self.dma.is_triggered(channel, DmaTiming::Special, &self.io)
+                    && self.dma.channels[channel].destination & !3 == address
+                {
+                    self.run_dma(channel);
+                }
+            }
+        }
+    }
+
+    /// Bus::run_dma
+    ///
+    /// Perform a whole DMA transfer. The cpu is stalled for the duration of the transfer, whose
+    /// cycles are added to its next access. Refills of the sound FIFOs (channels 1 and 2 with the
+    /// special timing) always transfer 4 words to the fixed address of the FIFO.
+    ///
+    /// @param channel [usize]: index of the channel
+    fn run_dma(&mut self, channel: usize) {
+        let control = Dma::control(channel, &self.io);
+        let sound_fifo =
+            (channel == 1 || channel == 2) && Dma::timing(control) == DmaTiming::Special;
+        let (mas, count, destination_control) = if sound_fifo {
+            (TransferSize::WORD, 4, 2)
+        } else {
+            (
+                Dma::transfer_size(control),
+                self.dma.channels[channel].count,
+                control.get_range(6, 5),
+            )
+        };
+        let alignment = if mas == TransferSize::WORD { !3 } else { !1 };
+        let state = self.dma.channels[channel];
+        let mut source = state.source & alignment;
+        let mut destination = state.destination & alignment;
+
+        // The length of the first transfer towards the EEPROM tells the width of its addresses
+        if channel == 3 && self.is_eeprom_address(destination) {
+            if let Backup::Eeprom(eeprom) = &mut self.backup {
+                eeprom.detect_address_bits(state.count);
+            }
+        }
+
+        for i in 0..count {
+            let mut data = self
+                .read(MemoryRequest {
+                    address: source,
+                    mas,
+                    n_opc: BusSignal::HIGH,
+                    ..Default::default()
+                })
+                .data;
+            if mas == TransferSize::HALFWORD {
+                data = ((data >> ((source & 2) * 8)) & 0xffff) * 0x00010001;
+            }
+            self.write(MemoryRequest {
+                address: destination,
+                data,
+                mas,
+                nr_w: BusSignal::HIGH,
+                n_opc: BusSignal::HIGH,
+                ..Default::default()
+            });
+
+            let sequential = i > 0;
+            self.dma_cycles += self.wait_control.access_cycles(source, mas, sequential)
+                + self
+                    .wait_control
+                    .access_cycles(destination, mas, sequential);
+            source = Dma::next_address(source, control.get_range(8, 7), mas);
+            destination = Dma::next_address(destination, destination_control, mas);
+        }
+
+        // Internal cycles to start and end the transfer
+        self.dma_cycles += 2;
+        self.dma.finish(channel, source, destination, &mut self.io);
+    }
+}
+
+#[cfg(test)]
+mod test_bus {
+
+    use crate::bus::{Bus, TransferSize, BIOS_OPCODE_AFTER_STARTUP};
+    use crate::cartridge::game_database::GameSettings;
+    use crate::cartridge::gpio::{GPIO_DATA, GPIO_DIRECTION};
+    use crate::cartridge::rtc::RtcClock;
+    use crate::io::registers::{IE, IF, WAITCNT};
+    use crate::memory::Memory;
+
+    /// Build a bus starting from the beginning of `rom`, with `bios` at 0x00000100.
+    fn bus_with_program(bios: &[u32], rom: &[u32]) -> Bus {
+        let mut bus = Bus::new();
+        for (index, opcode) in bios.iter().enumerate() {
+            bus.bios.write32(0x100 + index as u32 * 4, *opcode);
+        }
+        bus.gamepak = Memory::new(0x08000000, 0x400, true, String::from("GAMEPAK"));
+        for (index, opcode) in rom.iter().enumerate() {
+            bus.gamepak.write32(0x08000000 + index as u32 * 4, *opcode);
+        }
+
+        bus.set_headless(true);
+        bus
+    }
+
+    #[test]
+    fn test_mirrored_address() {
+        assert_eq!(Bus::mirrored_address(0x00001234), 0x00001234);
+        assert_eq!(Bus::mirrored_address(0x02fc0010), 0x02000010);
+        assert_eq!(Bus::mirrored_address(0x03ff8010), 0x03000010);
+        assert_eq!(Bus::mirrored_address(0x05000410), 0x05000010);
+        assert_eq!(Bus::mirrored_address(0x06020010), 0x06000010);
+        assert_eq!(Bus::mirrored_address(0x06017ffe), 0x06017ffe);
+        assert_eq!(Bus::mirrored_address(0x06018010), 0x06010010);
+        assert_eq!(Bus::mirrored_address(0x0603c010), 0x06014010);
+        assert_eq!(Bus::mirrored_address(0x07fffc10), 0x07000010);
+        assert_eq!(Bus::mirrored_address(0x0a000010), 0x08000010);
+        assert_eq!(Bus::mirrored_address(0x0dffffff), 0x09ffffff);
+        assert_eq!(Bus::mirrored_address(0x0f010010), 0x0e000010);
+        assert_eq!(Bus::mirrored_address(0x10000000), 0x10000000);
+    }
+
+    #[test]
+    fn test_sensors() {
+        let mut bus = Bus::new();
+        bus.set_light_level(0x80);
+        bus.set_tilt(0x3a0, 0x3a0);
+        assert_eq!(bus.light_level(), None);
+        assert_eq!(bus.tilt(), None);
+        assert!(!bus.is_rumbling());
+
+        let settings = GameSettings {
+            solar: true,
+            tilt: true,
+            rumble: true,
+            ..GameSettings::NONE
+        };
+        bus.configure(settings, RtcClock::Fixed(0));
+        bus.set_light_level(0x80);
+        bus.set_tilt(0x3a0, 0x3b0);
+        assert_eq!(bus.light_level(), Some(0x80));
+        assert_eq!(bus.tilt(), Some((0x3a0, 0x3b0)));
+
+        // The motor is driven by the pin 3 of the gpio port, once it is an output
+        bus.gpio.write(GPIO_DATA, 0x8, TransferSize::HALFWORD);
+        assert!(!bus.is_rumbling());
+        bus.gpio
+            .write(GPIO_DIRECTION, 0x00080008, TransferSize::HALFWORD);
+        bus.gpio.write(GPIO_DATA, 0x8, TransferSize::HALFWORD);
+        assert!(bus.is_rumbling());
+        bus.gpio.write(GPIO_DATA, 0x0, TransferSize::HALFWORD);
+        assert!(!bus.is_rumbling());
+    }
+
+    #[test]
+    fn test_idle_loop() {
+        // mov r0, #0; add r0, r0, #1; b 0x08000004
+        let mut bus = bus_with_program(&[], &[0xe3a00000, 0xe2800001, 0xeafffffd]);
+        let settings = GameSettings {
+            idle_loop: Some(0x08000004),
+            ..GameSettings::NONE
+        };
+        bus.configure(settings, RtcClock::Fixed(0));
+
+        // The cpu stops when it branches back to the loop, the first pass being sequential
+        (0..200).for_each(|_| bus.step());
+        assert_eq!(bus.cpu.rf.get_register(0, 0), 1);
+
+        // The interrupts which are not enabled don't resume it
+        bus.io.set(IF, 0x0001);
+        (0..200).for_each(|_| bus.step());
+        assert_eq!(bus.cpu.rf.get_register(0, 0), 1);
+
+        bus.io.set(IE, 0x0001);
+        (0..200).for_each(|_| bus.step());
+        assert!(bus.cpu.rf.get_register(0, 0) > 1);
+    }
+
+    #[test]
+    fn test_headless_capture() {
+        let dir = std::env::temp_dir().join("crusty_gba_test_headless_capture");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("game.wav");
+
+        // b 0x08000000, with the first poll of the keypad and the end of the frames
+        let mut bus = Bus::new();
+        bus.gamepak = Memory::new(0x08000000, 0x400, true, String::from("GAMEPAK"));
+        bus.gamepak.write32(0x08000000, 0xeafffffe);
+        bus.set_headless(true);
+        bus.start_audio_capture(&path, false).unwrap();
+        (0..3 * 280896).for_each(|_| bus.step());
+        bus.stop_audio_capture().unwrap();
+        assert!(bus.is_running());
+
+        // 3 frames at 32768 Hz
+        let wav = std::fs::read(&path).unwrap();
+        assert_eq!((wav.len() - 44) / 4, 3 * 280896 / 512);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_read_gamepak() {
+        let mut bus = bus_with_program(&[], &[]);
+        bus.gamepak = Memory::new(0x08000000, 0x10, true, String::from("GAMEPAK"));
+        bus.gamepak.write32(0x08000004, 0x12345678);
+
+        assert_eq!(bus.read_gamepak(0x08000004, TransferSize::WORD), 0x12345678);
+        assert_eq!(bus.read_gamepak(0x08000010, TransferSize::WORD), 0x00090008);
+
+        // The gpio registers of a small rom are not readable: the bus holds the address
+        assert_eq!(bus.read_gamepak(0x080000c4, TransferSize::WORD), 0x00630062);
+        assert_eq!(
+            bus.read_gamepak(0x080000c8, TransferSize::HALFWORD),
+            0x00650064
+        );
+    }
+
+    #[test]
+    fn test_bios_protected_from_loads() {
+        let mut bus = bus_with_program(
+            &[
+                0xe1a0f00e, // mov pc, lr
+                0xe1a04004, // mov r4, r4
+                0xe1a05005, // mov r5, r5
+            ],
+            &[
+                0xe3a01000, // mov r1, #0
+                0xe5912000, // ldr r2, [r1]
+                0xe3a00c01, // mov r0, #0x100
+                0xe1a0e00f, // mov lr, pc
+                0xe12fff10, // bx r0
+                0xe5913000, // ldr r3, [r1]
+                0xeafffffe, // b .
+            ],
+        );
+        (0..300).for_each(|_| bus.step());
+
+        // Loads from the bios with the program counter in the game pak get the last opcode
+        // fetched from the bios: the one after startup, then the one fetched while returning
+        assert_eq!(bus.cpu.rf.get_register(2, 0), BIOS_OPCODE_AFTER_STARTUP);
+        assert_eq!(bus.cpu.rf.get_register(3, 0), 0xe1a05005);
+    }
+
+    #[test]
+    fn test_open_bus_after_load() {
+        let mut bus = bus_with_program(
+            &[
+                0xe3a03901, // mov r3, #0x4000
+                0xe2433004, // sub r3, r3, #4
+                0xe8930030, // ldm r3, {r4, r5}
+                0xeafffffe, // b .
+                0xe1a06006, // mov r6, r6
+            ],
+            &[
+                0xe3a00c01, // mov r0, #0x100
+                0xe12fff10, // bx r0
+            ],
+        );
+        bus.bios.write32(0x3ffc, 0x12345678);
+        (0..100).for_each(|_| bus.step());
+
+        // Unused memory right after the bios reads the last opcode fetched, not the data of the
+        // previous access
+        assert_eq!(bus.cpu.rf.get_register(4, 0), 0x12345678);
+        assert_eq!(bus.cpu.rf.get_register(5, 0), 0xe1a06006);
+    }
+
+    #[test]
+    fn test_prefetch_discarded_by_load() {
+        let mut bus = bus_with_program(
+            &[],
+            &[
+                0xe3a01201, // mov r1, #0x10000000
+                0xe0030191, // mul r3, r1, r1
+                0xe0030191, // mul r3, r1, r1
+                0xe59f2000, // ldr r2, [pc]
+                0xeafffffe, // b .
+                0x12345678, // data
+            ],
+        );
+        bus.io.set(WAITCNT, 0x4000);
+        bus.wait_control.set_waitcnt(0x4000);
+        // Stop on the cycle completing the load
+        (0..47).for_each(|_| bus.step());
+        assert_eq!(bus.cpu.rf.get_register(2, 0), 0x12345678);
+
+        // The load discards the buffer filled during the multiplications, and the prefetching
+        // doesn't restart from the data: the next opcode is not in the buffer
+        let cycles = bus.prefetch.access(
+            0x08000018,
+            TransferSize::WORD,
+            true,
+            true,
+            &bus.wait_control,
+        );
+        assert_eq!(
+            cycles,
+            bus.wait_control
+                .access_cycles(0x08000018, TransferSize::WORD, true)
+        );
+    }
 }
@@ -0,0 +1,124 @@
+use crate::common::BitOperation;
+
+/// open_bus::OpenBus
+///
+/// Keeps track of the last opcodes fetched by the cpu, in order to provide the value returned by
+/// reads from unmapped addresses. From gbatek/gba-unpredictable-things, in arm state the value is
+/// the last prefetched opcode, while in thumb state it depends on the region containing the
+/// program counter:
+///
+/// Region               PC aligned to 4          PC not aligned to 4
+/// --------------------------------------------------------------------
+/// EWRAM, PRAM, VRAM,   LSW = [$+4]              LSW = [$+4]
+/// ROM                  MSW = [$+4]              MSW = [$+4]
+/// BIOS, OAM            LSW = [$+4]              LSW = [$+2]
+///                      MSW = [$+6]              MSW = [$+4]
+/// IWRAM                LSW = [$+4]              LSW = [$+2]
+///                      MSW = [$+2]              MSW = [$+4]
+/// --------------------------------------------------------------------
+///
+/// Where $+4 is the last fetched opcode.
+pub struct OpenBus {
+    last_fetch_address: u32,  // Address of the last opcode fetch
+    last_fetch_data: u32,     // Word containing the last fetched opcode
+    previous_fetch_data: u32, // Word containing the opcode fetched before the last one
+}
+
+impl OpenBus {
+    pub fn new() -> Self {
+        Self {
+            last_fetch_address: 0,
+            last_fetch_data: 0,
+            previous_fetch_data: 0,
+        }
+    }
+
+    /// OpenBus::record_fetch
+    ///
+    /// Store an opcode fetched by the cpu.
+    ///
+    /// @param address [u32]: address of the fetch
+    /// @param data [u32]: word returned by the bus, containing the opcode
+    pub fn record_fetch(&mut self, address: u32, data: u32) {
+        self.previous_fetch_data = self.last_fetch_data;
+        self.last_fetch_data = data;
+        self.last_fetch_address = address;
+    }
+
+    /// OpenBus::last_fetch_address
+    ///
+    /// @return [u32]: address of the last opcode fetched by the cpu
+    pub fn last_fetch_address(&self) -> u32 {
+        self.last_fetch_address
+    }
+
+    /// OpenBus::value
+    ///
+    /// @param thumb [bool]: whether the cpu is in thumb state
+    /// @return [u32]: value read from an unmapped address
+    pub fn value(&self, thumb: bool) -> u32 {
+        if !thumb {
+            return self.last_fetch_data;
+        }
+
+        let address = self.last_fetch_address;
+        let last_opcode = Self::halfword(self.last_fetch_data, address);
+
+        match address >> 24 {
+            // The word on the bus contains both [$+2] and [$+4] or [$+4] and [$+6], depending on
+            // the alignment
+            0x00 | 0x07 => self.last_fetch_data,
+            0x03 => {
+                if address.is_bit_clear(1) {
+                    let previous_opcode =
+                        Self::halfword(self.previous_fetch_data, address.wrapping_sub(2));
+                    last_opcode | (previous_opcode << 16)
+                } else {
+                    self.last_fetch_data
+                }
+            }
+            _ => last_opcode | (last_opcode << 16),
+        }
+    }
+
+    /// OpenBus::halfword
+    ///
+    /// @param data [u32]: word read from the bus
+    /// @param address [u32]: address of the halfword
+    /// @return [u32]: halfword of `data` corresponding to `address`
+    fn halfword(data: u32, address: u32) -> u32 {
+        if address.is_bit_set(1) {
+            data.get_range(31, 16)
+        } else {
+            data.get_range(15, 0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_open_bus {
+
+    use crate::bus::open_bus::OpenBus;
+
+    #[test]
+    fn test_open_bus() {
+        let mut open_bus = OpenBus::new();
+
+        open_bus.record_fetch(0x08000000, 0xe3a00001);
+        assert_eq!(open_bus.value(false), 0xe3a00001);
+
+        open_bus.record_fetch(0x08000104, 0x46c02001);
+        assert_eq!(open_bus.value(true), 0x20012001);
+        open_bus.record_fetch(0x08000106, 0x46c02001);
+        assert_eq!(open_bus.value(true), 0x46c046c0);
+
+        open_bus.record_fetch(0x00000106, 0x46c02001);
+        assert_eq!(open_bus.value(true), 0x46c02001);
+
+        open_bus.record_fetch(0x03000106, 0x11112222);
+        open_bus.record_fetch(0x03000108, 0x33334444);
+        assert_eq!(open_bus.value(true), 0x11114444);
+        open_bus.record_fetch(0x0300010a, 0x33334444);
+        assert_eq!(open_bus.value(true), 0x33334444);
+    }
+}
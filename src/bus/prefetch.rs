@@ -0,0 +1,160 @@
+use crate::bus::waitstates::WaitControl;
+use crate::bus::TransferSize;
+
+/// Maximum number of halfwords stored in the prefetch buffer
+const PREFETCH_BUFFER_SIZE: u32 = 8;
+
+/// prefetch::Prefetch
+///
+/// Emulation of the game pak prefetch buffer, enabled by bit 14 of WAITCNT. While the cpu is not
+/// using the game pak bus (internal cycles or accesses to other regions), the buffer reads the
+/// halfwords following the last opcode fetched from the ROM. Sequential opcode fetches which hit
+/// the buffer only require one cycle, while non-sequential accesses, branches and data accesses to
+/// the ROM discard its content.
+pub struct Prefetch {
+    active: bool,      // Whether the buffer is following a sequence of opcodes
+    next_address: u32, // Address of the first halfword in the buffer
+    count: u32,        // Number of halfwords in the buffer
+    cycles_left: u32,  // Cycles required to complete the halfword being fetched
+}
+
+impl Prefetch {
+    pub fn new() -> Self {
+        Self {
+            active: false,
+            next_address: 0,
+            count: 0,
+            cycles_left: 0,
+        }
+    }
+
+    /// Prefetch::discard
+    ///
+    /// Empty the buffer and stop prefetching until the next opcode fetch from the ROM.
+    pub fn discard(&mut self) {
+        self.active = false;
+        self.count = 0;
+    }
+
+    /// Prefetch::step
+    ///
+    /// Advance the buffer by one cycle in which the game pak bus is not used by the cpu.
+    ///
+    /// @param wait_control [&WaitControl]: waitstates configuration
+    pub fn step(&mut self, wait_control: &WaitControl) {
+        if !self.active || self.count >= PREFETCH_BUFFER_SIZE {
+            return;
+        }
+
+        self.cycles_left = self.cycles_left.saturating_sub(1);
+        if self.cycles_left == 0 {
+            self.count += 1;
+            self.cycles_left = self.halfword_cycles(wait_control);
+        }
+    }
+
+    /// Prefetch::access
+    ///
+    /// Handle an access from the cpu to the game pak ROM.
+    ///
+    /// @param address [u32]: address of the access
+    /// @param mas [TransferSize]: size of the access
+    /// @param is_opcode [bool]: whether the access is an opcode fetch
+    /// @param sequential [bool]: whether the access is sequential to the previous one
+    /// @param wait_control [&WaitControl]: waitstates configuration
+    /// @return [u32]: number of cycles of the access
+    pub fn access(
+        &mut self,
+        address: u32,
+        mas: TransferSize,
+        is_opcode: bool,
+        sequential: bool,
+        wait_control: &WaitControl,
+    ) -> u32 {
+        let halfwords = if mas == TransferSize::WORD { 2 } else { 1 };
+
+        // Data accesses use the game pak bus and make the content of the buffer useless
+        if !is_opcode {
+            self.discard();
+            return wait_control.access_cycles(address, mas, sequential);
+        }
+
+        // Buffer hit: the halfwords are already available or being fetched
+        if self.active && sequential && address == self.next_address {
+            self.next_address = address.wrapping_add(halfwords * 2);
+            if self.count >= halfwords {
+                self.count -= halfwords;
+                return 1;
+            }
+
+            // The first missing halfword is being fetched, the others require a full access
+            let missing = halfwords - self.count;
+            self.count = 0;
+            let cycles = self.cycles_left + (missing - 1) * self.halfword_cycles(wait_control);
+            self.cycles_left = self.halfword_cycles(wait_control);
+            return cycles.max(1);
+        }
+
+        // Buffer miss: a normal access is performed, and the buffer restarts from the next opcode
+        let cycles = wait_control.access_cycles(address, mas, sequential);
+        self.active = true;
+        self.count = 0;
+        self.next_address = address.wrapping_add(halfwords * 2);
+        self.cycles_left = self.halfword_cycles(wait_control);
+        cycles
+    }
+
+    /// Prefetch::halfword_cycles
+    ///
+    /// @param wait_control [&WaitControl]: waitstates configuration
+    /// @return [u32]: cycles required to fetch the next halfword which is not in the buffer
+    fn halfword_cycles(&self, wait_control: &WaitControl) -> u32 {
+        1 + wait_control.gamepak_waitstates(self.next_address.wrapping_add(self.count * 2), true)
+    }
+}
+
+#[cfg(test)]
+mod test_prefetch {
+
+    use crate::bus::prefetch::Prefetch;
+    use crate::bus::waitstates::WaitControl;
+    use crate::bus::TransferSize;
+
+    #[test]
+    fn test_prefetch() {
+        // Default waitstates for WS0: 4 non-sequential, 2 sequential
+        let wc = WaitControl::new();
+        let mut prefetch = Prefetch::new();
+        let hw = TransferSize::HALFWORD;
+
+        // First fetch is a miss
+        assert_eq!(prefetch.access(0x08000000, hw, true, false, &wc), 5);
+
+        // After 6 free cycles, 2 halfwords are in the buffer
+        (0..6).for_each(|_| prefetch.step(&wc));
+        assert_eq!(prefetch.access(0x08000002, hw, true, true, &wc), 1);
+        assert_eq!(prefetch.access(0x08000004, hw, true, true, &wc), 1);
+
+        // The buffer is empty: the halfword being fetched is waited for
+        assert_eq!(prefetch.access(0x08000006, hw, true, true, &wc), 3);
+
+        // Word fetch with one halfword available and one still to be fetched
+        (0..4).for_each(|_| prefetch.step(&wc));
+        let cycles = prefetch.access(0x08000008, TransferSize::WORD, true, true, &wc);
+        assert_eq!(cycles, 2);
+
+        // The buffer never contains more than 8 halfwords
+        (0..100).for_each(|_| prefetch.step(&wc));
+        for address in (0x0800000c..0x0800001c).step_by(2) {
+            assert_eq!(prefetch.access(address, hw, true, true, &wc), 1);
+        }
+        assert_eq!(prefetch.access(0x0800001c, hw, true, true, &wc), 3);
+
+        // Branches and data accesses discard the content of the buffer
+        (0..100).for_each(|_| prefetch.step(&wc));
+        assert_eq!(prefetch.access(0x08000100, hw, true, false, &wc), 5);
+        (0..100).for_each(|_| prefetch.step(&wc));
+        prefetch.access(0x08001000, TransferSize::WORD, false, false, &wc);
+        assert_eq!(prefetch.access(0x08000102, hw, true, true, &wc), 3);
+    }
+}
@@ -0,0 +1,191 @@
+use crate::bus::TransferSize;
+use crate::common::BitOperation;
+
+/// Waitstates of the first access to a region, selected by the 2 bits fields of WAITCNT
+const NON_SEQUENTIAL_WAITSTATES: [u32; 4] = [4, 3, 2, 8];
+
+/// waitstates::WaitControl
+///
+/// Handles the WAITCNT register and computes the number of cycles required by each memory access,
+/// depending on the accessed region, the size of the transfer and whether the access is sequential
+/// or not. Values are taken from gbatek/gba-memory-map.
+pub struct WaitControl {
+    waitcnt: u32,
+}
+
+impl WaitControl {
+    pub fn new() -> Self {
+        Self { waitcnt: 0 }
+    }
+
+    /// WaitControl::set_waitcnt
+    ///
+    /// Update the configuration after a write to WAITCNT.
+    ///
+    /// @param waitcnt [u32]: new value of WAITCNT
+    pub fn set_waitcnt(&mut self, waitcnt: u32) {
+        self.waitcnt = waitcnt;
+    }
+
+    /// WaitControl::waitcnt
+    ///
+    /// @return [u32]: current value of WAITCNT
+    pub fn waitcnt(&self) -> u32 {
+        self.waitcnt
+    }
+
+    /// WaitControl::is_prefetch_enabled
+    ///
+    /// @return [bool]: true if the game pak prefetch buffer is enabled
+    pub fn is_prefetch_enabled(&self) -> bool {
+        self.waitcnt().is_bit_set(14)
+    }
+
+    /// WaitControl::access_cycles
+    ///
+    /// Compute the number of cycles required by an access to the memory.
+    ///
+    /// @param address [u32]: address of the access
+    /// @param mas [TransferSize]: size of the access
+    /// @param sequential [bool]: whether the access is sequential to the previous one
+    /// @return [u32]: number of cycles of the access (1 if there are no waitstates)
+    pub fn access_cycles(&self, address: u32, mas: TransferSize, sequential: bool) -> u32 {
+        let is_word = mas == TransferSize::WORD;
+
+        match address >> 24 {
+            // EWRAM has a 16 bits bus with 2 waitstates
+            0x02 if is_word => 6,
+            0x02 => 3,
+
+            // Palette RAM and VRAM have a 16 bits bus
+            0x05 | 0x06 if is_word => 2,
+
+            // Game pak ROM, 16 bits bus. A 32 bits access is split in two halfword accesses, the
+            // second being always sequential. The first access to a 128KB block is always
+            // non-sequential.
+            0x08..=0x0d => {
+                let sequential = sequential && address.get_range(16, 0) != 0;
+                let first_access = 1 + self.gamepak_waitstates(address, sequential);
+                if is_word {
+                    first_access + 1 + self.gamepak_waitstates(address, true)
+                } else {
+                    first_access
+                }
+            }
+
+            // Game pak SRAM, 8 bits bus
+            0x0e | 0x0f => 1 + NON_SEQUENTIAL_WAITSTATES[self.waitcnt().get_range(1, 0) as usize],
+
+            // BIOS, IWRAM, I/O, OAM, unmapped regions and 8/16 bits accesses to palette RAM and VRAM
+            // have no waitstates
+            _ => 1,
+        }
+    }
+
+    /// WaitControl::gamepak_waitstates
+    ///
+    /// Get the waitstates of a 16 bits access to the game pak ROM, depending on the waitstate
+    /// window (WS0, WS1 or WS2) containing the address.
+    ///
+    /// @param address [u32]: address of the access
+    /// @param sequential [bool]: whether the access is sequential to the previous one
+    /// @return [u32]: number of waitstates
+    pub fn gamepak_waitstates(&self, address: u32, sequential: bool) -> u32 {
+        let waitcnt = self.waitcnt();
+
+        // Each window has the non-sequential waitstates at bits [first_bit + 1, first_bit] and
+        // the sequential waitstates at bit first_bit + 2, selecting among the given values
+        let (first_bit, sequential_waitstates) = match address >> 24 {
+            0x08 | 0x09 => (2, [2, 1]),
+            0x0a | 0x0b => (5, [4, 1]),
+            _ => (8, [8, 1]),
+        };
+
+        if sequential {
+            sequential_waitstates[waitcnt.get_range(first_bit + 2, first_bit + 2) as usize]
+        } else {
+            NON_SEQUENTIAL_WAITSTATES[waitcnt.get_range(first_bit + 1, first_bit) as usize]
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_waitstates {
+
+    use crate::bus::waitstates::WaitControl;
+    use crate::bus::TransferSize;
+
+    #[test]
+    fn test_access_cycles() {
+        let mut wait_control = WaitControl::new();
+
+        assert_eq!(
+            wait_control.access_cycles(0x03000000, TransferSize::WORD, false),
+            1
+        );
+        assert_eq!(
+            wait_control.access_cycles(0x02000000, TransferSize::WORD, true),
+            6
+        );
+        assert_eq!(
+            wait_control.access_cycles(0x02000000, TransferSize::BYTE, true),
+            3
+        );
+        assert_eq!(
+            wait_control.access_cycles(0x06000000, TransferSize::WORD, true),
+            2
+        );
+
+        // Default WAITCNT: 4/2 for WS0, 4/4 for WS1, 4/8 for WS2, 4 for SRAM
+        assert_eq!(
+            wait_control.access_cycles(0x08000100, TransferSize::HALFWORD, false),
+            5
+        );
+        assert_eq!(
+            wait_control.access_cycles(0x08000100, TransferSize::HALFWORD, true),
+            3
+        );
+        assert_eq!(
+            wait_control.access_cycles(0x08000100, TransferSize::WORD, false),
+            8
+        );
+        assert_eq!(
+            wait_control.access_cycles(0x0a000100, TransferSize::WORD, true),
+            10
+        );
+        assert_eq!(
+            wait_control.access_cycles(0x0c000100, TransferSize::WORD, true),
+            18
+        );
+        assert_eq!(
+            wait_control.access_cycles(0x0e000000, TransferSize::BYTE, true),
+            5
+        );
+
+        // A sequential access at the beginning of a 128KB block is non-sequential
+        assert_eq!(
+            wait_control.access_cycles(0x08020000, TransferSize::HALFWORD, true),
+            5
+        );
+
+        // WS0 3/1, WS1 2/1, WS2 8/1, SRAM 8
+        wait_control.set_waitcnt(0x47d7);
+        assert!(wait_control.is_prefetch_enabled());
+        assert_eq!(
+            wait_control.access_cycles(0x08000100, TransferSize::WORD, false),
+            6
+        );
+        assert_eq!(
+            wait_control.access_cycles(0x0a000100, TransferSize::HALFWORD, false),
+            3
+        );
+        assert_eq!(
+            wait_control.access_cycles(0x0c000100, TransferSize::WORD, true),
+            4
+        );
+        assert_eq!(
+            wait_control.access_cycles(0x0e000000, TransferSize::BYTE, false),
+            9
+        );
+    }
+}
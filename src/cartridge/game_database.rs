@@ -0,0 +1,250 @@
+use crate::backup::flash::FlashChip;
+use crate::backup::BackupType;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// game_database::GameSettings
+///
+/// Hardware of a game pak which cannot be detected from the rom.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct GameSettings {
+    pub save_type: Option<BackupType>,
+    pub rtc: bool,
+    pub solar: bool,
+    pub tilt: bool,
+    pub gyro: bool,
+    pub rumble: bool,
+    pub idle_loop: Option<u32>, // Address of the busy loop waiting for interrupts
+}
+
+impl GameSettings {
+    /// Settings of a game without any special hardware
+    pub const NONE: GameSettings = GameSettings {
+        save_type: None,
+        rtc: false,
+        solar: false,
+        tilt: false,
+        gyro: false,
+        rumble: false,
+        idle_loop: None,
+    };
+
+    /// GameSettings::apply
+    ///
+    /// Modify the settings with an entry of an override file, of the form `key` or `key=value`.
+    ///
+    /// @param entry [&str]: setting to change
+    /// @return [Result<(), String>]: error if the entry is not valid
+    fn apply(&mut self, entry: &str) -> Result<(), String> {
+        let (key, value) = match entry.split_once('=') {
+            Some((key, value)) => (key, Some(value)),
+            None => (entry, None),
+        };
+
+        let flag = || match value {
+            None | Some("yes") => Ok(true),
+            Some("no") => Ok(false),
+            Some(_) => Err(format!("{} expects yes or no", key)),
+        };
+
+        match key {
+            "save" => {
+                self.save_type = Some(value.ok_or("save requires a value")?.parse()?);
+            }
+            "idle" => {
+                let address = value.ok_or("idle requires a value")?;
+                let address = address.trim_start_matches("0x");
+                self.idle_loop = Some(
+                    u32::from_str_radix(address, 16)
+                        .map_err(|_| format!("invalid idle loop address {}", address))?,
+                );
+            }
+            "rtc" => self.rtc = flag()?,
+            "solar" => self.solar = flag()?,
+            "tilt" => self.tilt = flag()?,
+            "gyro" => self.gyro = flag()?,
+            "rumble" => self.rumble = flag()?,
+            _ => return Err(format!("unknown setting {}", key)),
+        }
+        Ok(())
+    }
+}
+
+/// Games whose hardware is known to be misdetected, by game code
+#[rustfmt::skip]
+const BUILT_IN_GAMES: &[(&str, GameSettings)] = &[
+    // Pokemon Ruby, Sapphire and Emerald
+    ("AXVE", GameSettings { save_type: Some(BackupType::Flash(FlashChip::Sanyo128K)), rtc: true, ..GameSettings::NONE }),
+    ("AXVJ", GameSettings { save_type: Some(BackupType::Flash(FlashChip::Sanyo128K)), rtc: true, ..GameSettings::NONE }),
+    ("AXVP", GameSettings { save_type: Some(BackupType::Flash(FlashChip::Sanyo128K)), rtc: true, ..GameSettings::NONE }),
+    ("AXPE", GameSettings { save_type: Some(BackupType::Flash(FlashChip::Sanyo128K)), rtc: true, ..GameSettings::NONE }),
+    ("AXPJ", GameSettings { save_type: Some(BackupType::Flash(FlashChip::Sanyo128K)), rtc: true, ..GameSettings::NONE }),
+    ("AXPP", GameSettings { save_type: Some(BackupType::Flash(FlashChip::Sanyo128K)), rtc: true, ..GameSettings::NONE }),
+    ("BPEE", GameSettings { save_type: Some(BackupType::Flash(FlashChip::Sanyo128K)), rtc: true, ..GameSettings::NONE }),
+    ("BPEJ", GameSettings { save_type: Some(BackupType::Flash(FlashChip::Sanyo128K)), rtc: true, ..GameSettings::NONE }),
+    ("BPEP", GameSettings { save_type: Some(BackupType::Flash(FlashChip::Sanyo128K)), rtc: true, ..GameSettings::NONE }),
+    // Pokemon FireRed and LeafGreen
+    ("BPRE", GameSettings { save_type: Some(BackupType::Flash(FlashChip::Sanyo128K)), ..GameSettings::NONE }),
+    ("BPRJ", GameSettings { save_type: Some(BackupType::Flash(FlashChip::Sanyo128K)), ..GameSettings::NONE }),
+    ("BPRP", GameSettings { save_type: Some(BackupType::Flash(FlashChip::Sanyo128K)), ..GameSettings::NONE }),
+    ("BPGE", GameSettings { save_type: Some(BackupType::Flash(FlashChip::Sanyo128K)), ..GameSettings::NONE }),
+    ("BPGJ", GameSettings { save_type: Some(BackupType::Flash(FlashChip::Sanyo128K)), ..GameSettings::NONE }),
+    ("BPGP", GameSettings { save_type: Some(BackupType::Flash(FlashChip::Sanyo128K)), ..GameSettings::NONE }),
+    // Sennen Kazoku
+    ("BKAJ", GameSettings { save_type: Some(BackupType::Flash(FlashChip::Sanyo128K)), rtc: true, ..GameSettings::NONE }),
+    // Boktai: The Sun Is in Your Hand, Boktai 2 and Shin Bokura no Taiyou
+    ("U3IE", GameSettings { save_type: Some(BackupType::Eeprom), rtc: true, solar: true, ..GameSettings::NONE }),
+    ("U3IJ", GameSettings { save_type: Some(BackupType::Eeprom), rtc: true, solar: true, ..GameSettings::NONE }),
+    ("U3IP", GameSettings { save_type: Some(BackupType::Eeprom), rtc: true, solar: true, ..GameSettings::NONE }),
+    ("U32E", GameSettings { save_type: Some(BackupType::Eeprom), rtc: true, solar: true, ..GameSettings::NONE }),
+    ("U32J", GameSettings { save_type: Some(BackupType::Eeprom), rtc: true, solar: true, ..GameSettings::NONE }),
+    ("U32P", GameSettings { save_type: Some(BackupType::Eeprom), rtc: true, solar: true, ..GameSettings::NONE }),
+    ("U33J", GameSettings { save_type: Some(BackupType::Eeprom), rtc: true, solar: true, ..GameSettings::NONE }),
+    // Koro Koro Puzzle Happy Panechu! and Yoshi Topsy-Turvy
+    ("KHPJ", GameSettings { save_type: Some(BackupType::Eeprom), tilt: true, ..GameSettings::NONE }),
+    ("KYGE", GameSettings { save_type: Some(BackupType::Eeprom), tilt: true, ..GameSettings::NONE }),
+    ("KYGJ", GameSettings { save_type: Some(BackupType::Eeprom), tilt: true, ..GameSettings::NONE }),
+    ("KYGP", GameSettings { save_type: Some(BackupType::Eeprom), tilt: true, ..GameSettings::NONE }),
+    // WarioWare: Twisted!
+    ("RZWE", GameSettings { save_type: Some(BackupType::Sram), gyro: true, rumble: true, ..GameSettings::NONE }),
+    ("RZWJ", GameSettings { save_type: Some(BackupType::Sram), gyro: true, rumble: true, ..GameSettings::NONE }),
+    ("RZWP", GameSettings { save_type: Some(BackupType::Sram), gyro: true, rumble: true, ..GameSettings::NONE }),
+    // Drill Dozer
+    ("V49E", GameSettings { save_type: Some(BackupType::Sram), rumble: true, ..GameSettings::NONE }),
+    ("V49J", GameSettings { save_type: Some(BackupType::Sram), rumble: true, ..GameSettings::NONE }),
+    ("V49P", GameSettings { save_type: Some(BackupType::Sram), rumble: true, ..GameSettings::NONE }),
+];
+
+/// game_database::GameDatabase
+///
+/// Settings of the games needing special hardware, by game code. The built-in entries can be
+/// extended or changed by a user file, where each line contains a game code followed by the
+/// settings to change, separated by spaces:
+///
+/// # Comment
+/// AXVE save=flash128 rtc idle=0x080008a6
+/// KYGE tilt=no
+///
+/// Settings: save=<save type, as for --save-type>, rtc, solar, tilt, gyro, rumble (optionally
+/// followed by =yes or =no) and idle=<hexadecimal address>.
+pub struct GameDatabase {
+    games: HashMap<String, GameSettings>,
+}
+
+impl GameDatabase {
+    /// GameDatabase::new
+    ///
+    /// Create a database with the built-in entries.
+    pub fn new() -> Self {
+        Self {
+            games: BUILT_IN_GAMES
+                .iter()
+                .map(|(code, settings)| (code.to_string(), *settings))
+                .collect(),
+        }
+    }
+
+    /// GameDatabase::load_overrides
+    ///
+    /// Apply the content of a user override file.
+    ///
+    /// @param path [&Path]: path of the file
+    /// @return [Result<(), String>]: error if the file cannot be read or is not valid
+    pub fn load_overrides(&mut self, path: &Path) -> Result<(), String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("unable to read {}: {}", path.display(), e))?;
+        self.apply_overrides(&content)
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// GameDatabase::apply_overrides
+    ///
+    /// @param content [&str]: content of an override file
+    /// @return [Result<(), String>]: error if the content is not valid
+    pub fn apply_overrides(&mut self, content: &str) -> Result<(), String> {
+        for (index, line) in content.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            let mut fields = line.split_whitespace();
+            let Some(code) = fields.next() else {
+                continue;
+            };
+
+            if code.len() != 4 {
+                return Err(format!("line {}: invalid game code {}", index + 1, code));
+            }
+
+            let settings = self
+                .games
+                .entry(code.to_string())
+                .or_insert(GameSettings::NONE);
+            for entry in fields {
+                settings
+                    .apply(entry)
+                    .map_err(|e| format!("line {}: {}", index + 1, e))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// GameDatabase::lookup
+    ///
+    /// @param game_code [&str]: game code from the header of the rom
+    /// @return [GameSettings]: settings of the game
+    pub fn lookup(&self, game_code: &str) -> GameSettings {
+        self.games
+            .get(game_code)
+            .copied()
+            .unwrap_or(GameSettings::NONE)
+    }
+}
+
+#[cfg(test)]
+mod test_game_database {
+
+    use crate::backup::flash::FlashChip;
+    use crate::backup::BackupType;
+    use crate::cartridge::game_database::{GameDatabase, GameSettings};
+
+    #[test]
+    fn test_lookup_and_overrides() {
+        let mut database = GameDatabase::new();
+        assert_eq!(database.lookup("ZZZZ"), GameSettings::NONE);
+
+        let emerald = database.lookup("BPEE");
+        assert_eq!(
+            emerald.save_type,
+            Some(BackupType::Flash(FlashChip::Sanyo128K))
+        );
+        assert!(emerald.rtc);
+
+        database
+            .apply_overrides(
+                "# Overrides\n\
+                 BPEE rtc=no idle=0x080008a6\n\
+                 BPRE save=flash128-macronix\n\
+                 \n\
+                 ZZZZ save=eeprom solar # custom build\n",
+            )
+            .unwrap();
+        let emerald = database.lookup("BPEE");
+        assert_eq!(
+            emerald.save_type,
+            Some(BackupType::Flash(FlashChip::Sanyo128K))
+        );
+        assert!(!emerald.rtc);
+        assert_eq!(emerald.idle_loop, Some(0x080008a6));
+        assert_eq!(
+            database.lookup("BPRE").save_type,
+            Some(BackupType::Flash(FlashChip::Macronix128K))
+        );
+        let custom = database.lookup("ZZZZ");
+        assert_eq!(custom.save_type, Some(BackupType::Eeprom));
+        assert!(custom.solar);
+
+        assert!(database.apply_overrides("BPEE save=tape").is_err());
+        assert!(database.apply_overrides("BPEE rtc=maybe").is_err());
+        assert!(database.apply_overrides("BPEE turbo").is_err());
+        assert!(database.apply_overrides("POKEMON rtc").is_err());
+    }
+}
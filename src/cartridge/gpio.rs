@@ -0,0 +1,167 @@
+use crate::bus::TransferSize;
+use crate::cartridge::rtc::Rtc;
+use crate::cartridge::sensors::{GyroSensor, Rumble, SolarSensor};
+use crate::common::BitOperation;
+
+/// Registers of the port, mapped over the rom
+pub const GPIO_DATA: u32 = 0x080000c4;
+pub const GPIO_DIRECTION: u32 = 0x080000c6;
+pub const GPIO_CONTROL: u32 = 0x080000c8;
+
+/// gpio::Gpio
+///
+/// 4 bits general purpose port of the game pak, used to connect additional hardware such as a
+/// real time clock, light or rotation sensors and a rumble motor. The registers are mapped over
+/// the rom at 0x080000c4 - 0x080000c9:
+///
+/// Register    Content
+/// -------------------------------------------------------
+/// Data        Value of the pins 0-3
+/// Direction   For each pin, 1 if written by the gba, 0 if read
+/// Control     Bit 0: registers readable (otherwise the rom is read)
+/// -------------------------------------------------------
+pub struct Gpio {
+    data: u32,
+    direction: u32,
+    readable: bool,
+    pub rtc: Option<Rtc>,
+    pub solar: Option<SolarSensor>,
+    pub gyro: Option<GyroSensor>,
+    pub rumble: Option<Rumble>,
+}
+
+impl Gpio {
+    pub fn new() -> Self {
+        Self {
+            data: 0,
+            direction: 0,
+            readable: false,
+            rtc: None,
+            solar: None,
+            gyro: None,
+            rumble: None,
+        }
+    }
+
+    /// Gpio::is_gpio_address
+    ///
+    /// @param address [u32]: address of the access, in the first waitstate window
+    /// @return [bool]: true if the access involves the registers of the port
+    pub fn is_gpio_address(address: u32) -> bool {
+        (GPIO_DATA..=GPIO_CONTROL + 1).contains(&address)
+    }
+
+    /// Gpio::read
+    ///
+    /// @param address [u32]: address of the access
+    /// @param rom_data [u32]: word of the rom at the address
+    /// @return [u32]: word of the rom, with the registers replacing its halfwords if readable
+    pub fn read(&self, address: u32, rom_data: u32) -> u32 {
+        if !self.readable {
+            return rom_data;
+        }
+
+        let word_address = address & !3;
+        [0, 16].iter().fold(rom_data, |data, shift| {
+            let value = match word_address + shift / 8 {
+                GPIO_DATA => self.pins(),
+                GPIO_DIRECTION => self.direction,
+                GPIO_CONTROL => self.readable as u32,
+                _ => return data,
+            };
+            (data & !(0xffff << shift)) | (value << shift)
+        })
+    }
+
+    /// Gpio::write
+    ///
+    /// @param address [u32]: address of the access
+    /// @param data [u32]: data on the bus
+    /// @param mas [TransferSize]: size of the access
+    pub fn write(&mut self, address: u32, data: u32, mas: TransferSize) {
+        match mas {
+            TransferSize::WORD => {
+                self.write_halfword(address & !3, data & 0xffff);
+                self.write_halfword((address & !3) + 2, data >> 16);
+            }
+            TransferSize::HALFWORD => {
+                self.write_halfword(address & !1, (data >> ((address & 2) * 8)) & 0xffff)
+            }
+            // Only the lower byte of the registers is used
+            TransferSize::BYTE if address.is_bit_clear(0) => {
+                self.write_halfword(address, (data >> ((address & 3) * 8)) & 0xff)
+            }
+            TransferSize::BYTE => {}
+        }
+    }
+
+    /// Gpio::poll_irq
+    ///
+    /// @return [bool]: true if a device raised the game pak interrupt since the last call
+    pub fn poll_irq(&mut self) -> bool {
+        self.rtc.as_mut().is_some_and(|rtc| rtc.poll_irq())
+    }
+
+    /// Gpio::write_halfword
+    ///
+    /// @param address [u32]: address of the register
+    /// @param value [u32]: value to write
+    fn write_halfword(&mut self, address: u32, value: u32) {
+        match address {
+            GPIO_DATA => {
+                self.data = value & 0xf;
+                let pins = self.pins();
+                if let Some(rtc) = self.rtc.as_mut() {
+                    rtc.write_pins(pins);
+                }
+                if let Some(solar) = self.solar.as_mut() {
+                    solar.write_pins(pins);
+                }
+                if let Some(gyro) = self.gyro.as_mut() {
+                    gyro.write_pins(pins);
+                }
+                if let Some(rumble) = self.rumble.as_mut() {
+                    rumble.write_pins(pins);
+                }
+            }
+            GPIO_DIRECTION => self.direction = value & 0xf,
+            GPIO_CONTROL => self.readable = value.is_bit_set(0),
+            _ => {}
+        }
+    }
+
+    /// Gpio::pins
+    ///
+    /// @return [u32]: value of the pins, driven by the gba for the outputs and by the devices for
+    /// the inputs
+    fn pins(&self) -> u32 {
+        let devices = self.rtc.as_ref().map_or(0, |rtc| rtc.read_pins())
+            | self.solar.as_ref().map_or(0, |solar| solar.read_pins())
+            | self.gyro.as_ref().map_or(0, |gyro| gyro.read_pins());
+        (self.data & self.direction) | (devices & !self.direction & 0xf)
+    }
+}
+
+#[cfg(test)]
+mod test_gpio {
+
+    use crate::bus::TransferSize;
+    use crate::cartridge::gpio::{Gpio, GPIO_CONTROL, GPIO_DATA, GPIO_DIRECTION};
+
+    #[test]
+    fn test_registers() {
+        let mut gpio = Gpio::new();
+
+        gpio.write(GPIO_DIRECTION, 0x00070007, TransferSize::HALFWORD);
+        gpio.write(GPIO_DATA, 0x000d000d, TransferSize::HALFWORD);
+
+        // The registers are write-only until enabled
+        assert_eq!(gpio.read(GPIO_DATA, 0x12345678), 0x12345678);
+        gpio.write(GPIO_CONTROL, 0x01010101, TransferSize::BYTE);
+        assert_eq!(gpio.read(GPIO_DATA, 0x12345678), 0x00070005);
+        assert_eq!(gpio.read(GPIO_CONTROL, 0x12345678), 0x12340001);
+
+        gpio.write(GPIO_DATA, 0x000f0003, TransferSize::WORD);
+        assert_eq!(gpio.read(GPIO_DIRECTION, 0), 0x000f0003);
+    }
+}
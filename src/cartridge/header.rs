@@ -0,0 +1,201 @@
+use crate::common::BitOperation;
+use std::fmt;
+
+/// Size of the header at the beginning of the rom
+pub const HEADER_SIZE: usize = 0xc0;
+
+/// Compressed Nintendo logo, which the bios checks before starting the game
+#[rustfmt::skip]
+pub const NINTENDO_LOGO: [u8; 156] = [
+    0x24, 0xff, 0xae, 0x51, 0x69, 0x9a, 0xa2, 0x21, 0x3d, 0x84, 0x82, 0x0a,
+    0x84, 0xe4, 0x09, 0xad, 0x11, 0x24, 0x8b, 0x98, 0xc0, 0x81, 0x7f, 0x21,
+    0xa3, 0x52, 0xbe, 0x19, 0x93, 0x09, 0xce, 0x20, 0x10, 0x46, 0x4a, 0x4a,
+    0xf8, 0x27, 0x31, 0xec, 0x58, 0xc7, 0xe8, 0x33, 0x82, 0xe3, 0xce, 0xbf,
+    0x85, 0xf4, 0xdf, 0x94, 0xce, 0x4b, 0x09, 0xc1, 0x94, 0x56, 0x8a, 0xc0,
+    0x13, 0x72, 0xa7, 0xfc, 0x9f, 0x84, 0x4d, 0x73, 0xa3, 0xca, 0x9a, 0x61,
+    0x58, 0x97, 0xa3, 0x27, 0xfc, 0x03, 0x98, 0x76, 0x23, 0x1d, 0xc7, 0x61,
+    0x03, 0x04, 0xae, 0x56, 0xbf, 0x38, 0x84, 0x00, 0x40, 0xa7, 0x0e, 0xfd,
+    0xff, 0x52, 0xfe, 0x03, 0x6f, 0x95, 0x30, 0xf1, 0x97, 0xfb, 0xc0, 0x85,
+    0x60, 0xd6, 0x80, 0x25, 0xa9, 0x63, 0xbe, 0x03, 0x01, 0x4e, 0x38, 0xe2,
+    0xf9, 0xa2, 0x34, 0xff, 0xbb, 0x3e, 0x03, 0x44, 0x78, 0x00, 0x90, 0xcb,
+    0x88, 0x11, 0x3a, 0x94, 0x65, 0xc0, 0x7c, 0x63, 0x87, 0xf0, 0x3c, 0xaf,
+    0xd6, 0x25, 0xe4, 0x8b, 0x38, 0x0a, 0xac, 0x72, 0x21, 0xd4, 0xf8, 0x07,
+];
+
+/// header::CartridgeHeader
+///
+/// Header of the game pak rom, from gbatek/gba-cartridge-header:
+///
+/// Offset      Size    Content
+/// -------------------------------------------------------
+/// 0x00        4       ARM branch to the entry point
+/// 0x04        156     Compressed Nintendo logo
+/// 0xa0        12      Title, uppercase ascii
+/// 0xac        4       Game code
+/// 0xb0        2       Maker code
+/// 0xb2        1       Fixed value 0x96
+/// 0xb3        1       Main unit code
+/// 0xb4        1       Device type
+/// 0xbc        1       Software version
+/// 0xbd        1       Complement check of 0xa0 - 0xbc
+/// -------------------------------------------------------
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct CartridgeHeader {
+    pub entry_opcode: u32,
+    pub logo: Vec<u8>,
+    pub title: String,
+    pub game_code: String,
+    pub maker_code: String,
+    pub fixed_value: u8,
+    pub unit_code: u8,
+    pub device_type: u8,
+    pub version: u8,
+    pub complement_check: u8,
+    computed_check: u8,
+}
+
+impl CartridgeHeader {
+    /// CartridgeHeader::parse
+    ///
+    /// @param rom [&[u8]]: content of the rom
+    /// @return [Option<CartridgeHeader>]: parsed header, or None if the rom is too small
+    pub fn parse(rom: &[u8]) -> Option<Self> {
+        let header = rom.get(..HEADER_SIZE)?;
+        let text = |range: std::ops::Range<usize>| {
+            String::from_utf8_lossy(&header[range])
+                .trim_end_matches('\0')
+                .to_string()
+        };
+
+        Some(Self {
+            entry_opcode: u32::from_le_bytes([header[0], header[1], header[2], header[3]]),
+            logo: header[0x04..0xa0].to_vec(),
+            title: text(0xa0..0xac),
+            game_code: text(0xac..0xb0),
+            maker_code: text(0xb0..0xb2),
+            fixed_value: header[0xb2],
+            unit_code: header[0xb3],
+            device_type: header[0xb4],
+            version: header[0xbc],
+            complement_check: header[0xbd],
+            computed_check: Self::complement_check(header),
+        })
+    }
+
+    /// CartridgeHeader::complement_check
+    ///
+    /// @param header [&[u8]]: header of the rom
+    /// @return [u8]: value expected by the bios at 0xbd
+    pub fn complement_check(header: &[u8]) -> u8 {
+        header[0xa0..0xbd]
+            .iter()
+            .fold(0_u8, |sum, byte| sum.wrapping_sub(*byte))
+            .wrapping_sub(0x19)
+    }
+
+    /// CartridgeHeader::entry_point
+    ///
+    /// @return [Option<u32>]: address the game starts from, if the first opcode is a branch
+    pub fn entry_point(&self) -> Option<u32> {
+        if self.entry_opcode.get_range(27, 24) != 0b1010 {
+            return None;
+        }
+
+        let offset = ((self.entry_opcode.get_range(23, 0) << 8) as i32 >> 6) as u32;
+        Some(0x08000008_u32.wrapping_add(offset))
+    }
+
+    /// CartridgeHeader::is_logo_valid
+    ///
+    /// @return [bool]: true if the header contains the Nintendo logo
+    pub fn is_logo_valid(&self) -> bool {
+        self.logo == NINTENDO_LOGO
+    }
+
+    /// CartridgeHeader::is_checksum_valid
+    ///
+    /// @return [bool]: true if the complement check matches the content of the header
+    pub fn is_checksum_valid(&self) -> bool {
+        self.complement_check == self.computed_check
+    }
+
+    /// CartridgeHeader::is_valid
+    ///
+    /// @return [bool]: true if the header would be accepted by the bios
+    pub fn is_valid(&self) -> bool {
+        self.is_logo_valid() && self.is_checksum_valid()
+    }
+}
+
+impl fmt::Display for CartridgeHeader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let entry_point = match self.entry_point() {
+            Some(address) => format!("{:#010x}", address),
+            None => format!("invalid branch {:#010x}", self.entry_opcode),
+        };
+        let validity = |valid: bool| if valid { "valid" } else { "INVALID" };
+
+        writeln!(f, "Title:        {}", self.title)?;
+        writeln!(f, "Game code:    {}", self.game_code)?;
+        writeln!(f, "Maker code:   {}", self.maker_code)?;
+        writeln!(f, "Version:      {}", self.version)?;
+        writeln!(f, "Unit code:    {:#04x}", self.unit_code)?;
+        writeln!(f, "Device type:  {:#04x}", self.device_type)?;
+        writeln!(f, "Entry point:  {}", entry_point)?;
+        writeln!(f, "Logo:         {}", validity(self.is_logo_valid()))?;
+        write!(
+            f,
+            "Checksum:     {:#04x} ({})",
+            self.complement_check,
+            validity(self.is_checksum_valid())
+        )
+    }
+}
+
+#[cfg(test)]
+mod test_header {
+
+    use crate::cartridge::header::{CartridgeHeader, HEADER_SIZE, NINTENDO_LOGO};
+
+    #[test]
+    fn test_parse() {
+        let mut rom = vec![0_u8; HEADER_SIZE];
+        assert!(CartridgeHeader::parse(&rom[..HEADER_SIZE - 1]).is_none());
+
+        // b 0x080000c0
+        rom[0..4].copy_from_slice(&0xea00002e_u32.to_le_bytes());
+        rom[0x04..0xa0].copy_from_slice(&NINTENDO_LOGO);
+        rom[0xa0..0xa8].copy_from_slice(b"TESTGAME");
+        rom[0xac..0xb2].copy_from_slice(b"ATSE01");
+        rom[0xb2] = 0x96;
+        rom[0xbc] = 2;
+        rom[0xbd] = CartridgeHeader::complement_check(&rom);
+
+        let header = CartridgeHeader::parse(&rom).unwrap();
+        assert_eq!(header.title, "TESTGAME");
+        assert_eq!(header.game_code, "ATSE");
+        assert_eq!(header.maker_code, "01");
+        assert_eq!(header.version, 2);
+        assert_eq!(header.entry_point(), Some(0x080000c0));
+        assert!(header.is_valid());
+
+        // Branch backwards
+        rom[0..4].copy_from_slice(&0xeafffffe_u32.to_le_bytes());
+        assert_eq!(
+            CartridgeHeader::parse(&rom).unwrap().entry_point(),
+            Some(0x08000000)
+        );
+
+        rom[0xa0] = b'X';
+        let header = CartridgeHeader::parse(&rom).unwrap();
+        assert!(header.is_logo_valid());
+        assert!(!header.is_checksum_valid());
+
+        rom[0xa0] = b'T';
+        rom[0x10] ^= 0xff;
+        let header = CartridgeHeader::parse(&rom).unwrap();
+        assert!(!header.is_logo_valid());
+        assert!(header.is_checksum_valid());
+        assert!(!header.is_valid());
+    }
+}
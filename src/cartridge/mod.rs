@@ -0,0 +1,5 @@
+pub mod game_database;
+pub mod gpio;
+pub mod header;
+pub mod rtc;
+pub mod sensors;
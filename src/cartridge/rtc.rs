@@ -0,0 +1,528 @@
+use crate::common::BitOperation;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Pins of the gpio port connected to the rtc
+const SCK: u32 = 0;
+const SIO: u32 = 1;
+const CS: u32 = 2;
+
+/// Writable bits of the status register: IRQ duty (1), per-minute IRQs (3, 5) and 24-hour mode (6)
+const STATUS_WRITE_MASK: u8 = 0x6a;
+/// Status bit selecting the 24-hour mode
+const STATUS_24_HOURS: u32 = 6;
+
+/// rtc::RtcClock
+///
+/// Source of the time of the rtc, as seconds since 1970-01-01 00:00:00. The host clock is read in
+/// UTC, with an offset which is changed when the game sets the time. A fixed time keeps the
+/// emulation deterministic.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RtcClock {
+    Host { offset: i64 },
+    Fixed(i64),
+}
+
+impl RtcClock {
+    /// RtcClock::now
+    ///
+    /// @return [i64]: current time of the clock
+    pub fn now(&self) -> i64 {
+        match self {
+            RtcClock::Host { offset } => {
+                let host = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|duration| duration.as_secs() as i64)
+                    .unwrap_or(0);
+                host + offset
+            }
+            RtcClock::Fixed(time) => *time,
+        }
+    }
+
+    /// RtcClock::set
+    ///
+    /// @param time [i64]: new current time of the clock
+    pub fn set(&mut self, time: i64) {
+        let now = self.now();
+        match self {
+            RtcClock::Host { offset } => *offset += time - now,
+            RtcClock::Fixed(fixed) => *fixed = time,
+        }
+    }
+
+    /// RtcClock::parse_datetime
+    ///
+    /// @param text [&str]: date and time in the format `YYYY-MM-DDTHH:MM:SS`
+    /// @return [Result<i64, String>]: corresponding time, or an error if the text is not valid
+    pub fn parse_datetime(text: &str) -> Result<i64, String> {
+        let error = || format!("invalid date {}, expected YYYY-MM-DDTHH:MM:SS", text);
+        let fields = text
+            .split(['-', 'T', ':'])
+            .map(|field| field.parse::<i64>().map_err(|_| error()))
+            .collect::<Result<Vec<i64>, String>>()?;
+
+        match fields[..] {
+            [year, month @ 1..=12, day @ 1..=31, hour @ 0..=23, minute @ 0..=59, second @ 0..=59] => {
+                Ok(days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second)
+            }
+            _ => Err(error()),
+        }
+    }
+}
+
+/// rtc::DateTime
+///
+/// Calendar date and time of the rtc.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct DateTime {
+    pub year: i64,
+    pub month: i64,
+    pub day: i64,
+    pub weekday: i64, // 0 is sunday
+    pub hour: i64,
+    pub minute: i64,
+    pub second: i64,
+}
+
+impl DateTime {
+    /// DateTime::from_timestamp
+    ///
+    /// @param time [i64]: seconds since 1970-01-01 00:00:00
+    /// @return [DateTime]: corresponding date and time
+    pub fn from_timestamp(time: i64) -> Self {
+        let days = time.div_euclid(86400);
+        let seconds = time.rem_euclid(86400);
+        let (year, month, day) = civil_from_days(days);
+
+        Self {
+            year,
+            month,
+            day,
+            // 1970-01-01 was a thursday
+            weekday: (days + 4).rem_euclid(7),
+            hour: seconds / 3600,
+            minute: seconds / 60 % 60,
+            second: seconds % 60,
+        }
+    }
+
+    /// DateTime::timestamp
+    ///
+    /// @return [i64]: seconds since 1970-01-01 00:00:00
+    pub fn timestamp(&self) -> i64 {
+        days_from_civil(self.year, self.month, self.day) * 86400
+            + self.hour * 3600
+            + self.minute * 60
+            + self.second
+    }
+}
+
+/// rtc::days_from_civil
+///
+/// Convert a date of the proleptic gregorian calendar to a number of days since 1970-01-01, from
+/// http://howardhinnant.github.io/date_algorithms.html.
+///
+/// @param year [i64]: year
+/// @param month [i64]: month, from 1 to 12
+/// @param day [i64]: day of the month, from 1 to 31
+/// @return [i64]: days since 1970-01-01
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// rtc::civil_from_days
+///
+/// Inverse of `days_from_civil`.
+///
+/// @param days [i64]: days since 1970-01-01
+/// @return [(i64, i64, i64)]: year, month and day
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// rtc::to_bcd
+///
+/// @param value [i64]: value from 0 to 99
+/// @return [u8]: value in binary coded decimal
+fn to_bcd(value: i64) -> u8 {
+    (((value / 10 % 10) << 4) | (value % 10)) as u8
+}
+
+/// rtc::from_bcd
+///
+/// @param value [u8]: value in binary coded decimal
+/// @return [i64]: binary value
+fn from_bcd(value: u8) -> i64 {
+    (value >> 4) as i64 * 10 + (value & 0xf) as i64
+}
+
+/// rtc::RtcCommand
+///
+/// Registers of the rtc, selected by bits 1-3 of the command byte.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum RtcCommand {
+    Reset,
+    Status,
+    DateTime,
+    Time,
+    ForceIrq,
+    Unused,
+}
+
+impl RtcCommand {
+    fn from_index(index: u8) -> Self {
+        match index {
+            0 => RtcCommand::Reset,
+            1 => RtcCommand::Status,
+            2 => RtcCommand::DateTime,
+            3 => RtcCommand::Time,
+            4 => RtcCommand::ForceIrq,
+            _ => RtcCommand::Unused,
+        }
+    }
+
+    /// RtcCommand::length
+    ///
+    /// @return [usize]: number of data bytes following the command
+    fn length(&self) -> usize {
+        match self {
+            RtcCommand::Status => 1,
+            RtcCommand::DateTime => 7,
+            RtcCommand::Time => 3,
+            _ => 0,
+        }
+    }
+}
+
+/// rtc::Rtc
+///
+/// Seiko S-3511 real time clock, connected to the gpio port with SCK on pin 0, SIO on pin 1 and
+/// CS on pin 2. A transfer starts when CS goes high, and each bit is clocked by a rising edge of
+/// SCK. The game first sends a command byte, most significant bit first:
+///
+/// Bits        Content
+/// -------------------------------------------------------
+/// 7 - 4       Fixed code 0110
+/// 3 - 1       Register: 0 reset, 1 status, 2 date and time, 3 time, 4 force IRQ
+/// 0           1 to read the register, 0 to write it
+/// -------------------------------------------------------
+///
+/// The data bytes of the register follow, least significant bit first. Date and time are
+/// stored in BCD as year (0-99, from 2000), month, day, day of the week, hour, minute and second.
+/// In 12-hour mode, bit 6 of the hour is set in the afternoon. The interrupts are signaled to the
+/// game pak IRQ line.
+pub struct Rtc {
+    pub clock: RtcClock,
+    status: u8,
+    pins: u32,
+    command: Option<(RtcCommand, bool)>,
+    shift: u8,
+    bit_count: usize,
+    data: Vec<u8>,
+    output: u32,
+    irq: bool,
+    last_minute: i64,
+}
+
+impl Rtc {
+    pub fn new(clock: RtcClock) -> Self {
+        Self {
+            clock,
+            status: 1 << STATUS_24_HOURS,
+            pins: 0,
+            command: None,
+            shift: 0,
+            bit_count: 0,
+            data: Vec::new(),
+            output: 1,
+            irq: false,
+            last_minute: clock.now().div_euclid(60),
+        }
+    }
+
+    /// Rtc::read_pins
+    ///
+    /// @return [u32]: value driven by the rtc on the pins of the port
+    pub fn read_pins(&self) -> u32 {
+        self.output << SIO
+    }
+
+    /// Rtc::write_pins
+    ///
+    /// @param pins [u32]: value of the pins of the port
+    pub fn write_pins(&mut self, pins: u32) {
+        let previous = self.pins;
+        self.pins = pins;
+
+        if pins.is_bit_clear(CS) {
+            self.command = None;
+            self.shift = 0;
+            self.bit_count = 0;
+            self.output = 1;
+            return;
+        }
+
+        if previous.is_bit_set(CS) && previous.is_bit_clear(SCK) && pins.is_bit_set(SCK) {
+            self.clock_bit(pins.get_range(SIO, SIO) as u8);
+        }
+    }
+
+    /// Rtc::poll_irq
+    ///
+    /// @return [bool]: true if the rtc raised an interrupt since the last call
+    pub fn poll_irq(&mut self) -> bool {
+        let minute = self.clock.now().div_euclid(60);
+        let minute_elapsed = minute != self.last_minute;
+        self.last_minute = minute;
+
+        let per_minute = (self.status as u32).is_bit_set(3) || (self.status as u32).is_bit_set(5);
+        let irq = self.irq || (per_minute && minute_elapsed);
+        self.irq = false;
+        irq
+    }
+
+    /// Rtc::clock_bit
+    ///
+    /// Handle a rising edge of SCK during a transfer.
+    ///
+    /// @param sio [u8]: value of SIO
+    fn clock_bit(&mut self, sio: u8) {
+        let Some((command, read)) = self.command else {
+            self.shift = (self.shift << 1) | sio;
+            self.bit_count += 1;
+            if self.bit_count == 8 {
+                self.start_command(self.shift);
+            }
+            return;
+        };
+
+        let byte = self.bit_count / 8;
+        if read {
+            self.output =
+                self.data
+                    .get(byte)
+                    .map_or(1, |data| (data >> (self.bit_count % 8)) & 1) as u32;
+            self.bit_count += 1;
+        } else if byte < command.length() {
+            if self.bit_count.is_multiple_of(8) {
+                self.data.push(0);
+            }
+            self.data[byte] |= sio << (self.bit_count % 8);
+            self.bit_count += 1;
+            if self.bit_count == command.length() * 8 {
+                self.write_register(command);
+            }
+        }
+    }
+
+    /// Rtc::start_command
+    ///
+    /// @param byte [u8]: command byte. Some games send it in the reverse bit order, which is
+    /// recognized from the position of the fixed code.
+    fn start_command(&mut self, byte: u8) {
+        let byte = if byte >> 4 != 0b0110 && byte & 0xf == 0b0110 {
+            byte.reverse_bits()
+        } else {
+            byte
+        };
+
+        let command = RtcCommand::from_index((byte >> 1) & 7);
+        let read = byte & 1 == 1;
+        self.command = Some((command, read));
+        self.bit_count = 0;
+        self.data = if read {
+            self.read_register(command)
+        } else {
+            Vec::new()
+        };
+
+        match command {
+            RtcCommand::Reset => {
+                // Back to 2000-01-01 00:00:00 in 12-hour mode
+                self.status = 0;
+                self.set_time(days_from_civil(2000, 1, 1) * 86400);
+            }
+            RtcCommand::ForceIrq => self.irq = true,
+            _ => {}
+        }
+    }
+
+    /// Rtc::read_register
+    ///
+    /// @param command [RtcCommand]: register to read
+    /// @return [Vec<u8>]: content of the register
+    fn read_register(&self, command: RtcCommand) -> Vec<u8> {
+        let now = DateTime::from_timestamp(self.clock.now());
+        let hour = if (self.status as u32).is_bit_set(STATUS_24_HOURS) {
+            to_bcd(now.hour)
+        } else {
+            to_bcd(now.hour % 12) | if now.hour >= 12 { 0x40 } else { 0 }
+        };
+        let time = [hour, to_bcd(now.minute), to_bcd(now.second)];
+
+        match command {
+            RtcCommand::Status => vec![self.status],
+            RtcCommand::DateTime => {
+                let mut data = vec![
+                    to_bcd(now.year - 2000),
+                    to_bcd(now.month),
+                    to_bcd(now.day),
+                    to_bcd(now.weekday),
+                ];
+                data.extend_from_slice(&time);
+                data
+            }
+            RtcCommand::Time => time.to_vec(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Rtc::write_register
+    ///
+    /// @param command [RtcCommand]: register written by the game, with the content in `data`
+    fn write_register(&mut self, command: RtcCommand) {
+        let mut now = DateTime::from_timestamp(self.clock.now());
+        let time = match command {
+            RtcCommand::Status => {
+                self.status = self.data[0] & STATUS_WRITE_MASK;
+                return;
+            }
+            RtcCommand::DateTime => {
+                now.year = 2000 + from_bcd(self.data[0]);
+                now.month = from_bcd(self.data[1]).clamp(1, 12);
+                now.day = from_bcd(self.data[2]).clamp(1, 31);
+                &self.data[4..7]
+            }
+            RtcCommand::Time => &self.data[0..3],
+            _ => return,
+        };
+
+        let pm = time[0] & 0x40 != 0 && (self.status as u32).is_bit_clear(STATUS_24_HOURS);
+        now.hour = from_bcd(time[0] & 0x3f) % 24 + if pm { 12 } else { 0 };
+        now.minute = from_bcd(time[1]) % 60;
+        now.second = from_bcd(time[2]) % 60;
+        self.set_time(now.timestamp());
+    }
+
+    /// Rtc::set_time
+    ///
+    /// Change the time of the clock, without triggering the per-minute interrupt.
+    ///
+    /// @param time [i64]: new time of the clock
+    fn set_time(&mut self, time: i64) {
+        self.clock.set(time);
+        self.last_minute = time.div_euclid(60);
+    }
+}
+
+#[cfg(test)]
+mod test_rtc {
+
+    use crate::cartridge::rtc::{DateTime, Rtc, RtcClock};
+
+    /// Send bits to the rtc, clocking each of them with SCK as games do
+    fn send(rtc: &mut Rtc, bits: &[u8]) {
+        for bit in bits {
+            rtc.write_pins(0b100 | (bit << 1) as u32);
+            rtc.write_pins(0b101 | (bit << 1) as u32);
+        }
+    }
+
+    fn send_byte_msb_first(rtc: &mut Rtc, byte: u8) {
+        let bits: Vec<u8> = (0..8).rev().map(|i| (byte >> i) & 1).collect();
+        send(rtc, &bits);
+    }
+
+    fn send_byte_lsb_first(rtc: &mut Rtc, byte: u8) {
+        let bits: Vec<u8> = (0..8).map(|i| (byte >> i) & 1).collect();
+        send(rtc, &bits);
+    }
+
+    fn receive_byte(rtc: &mut Rtc) -> u8 {
+        (0..8).fold(0, |byte, i| {
+            rtc.write_pins(0b100);
+            rtc.write_pins(0b101);
+            byte | ((((rtc.read_pins() >> 1) & 1) as u8) << i)
+        })
+    }
+
+    fn start(rtc: &mut Rtc, command: u8) {
+        rtc.write_pins(0b001);
+        rtc.write_pins(0b101);
+        send_byte_msb_first(rtc, command);
+    }
+
+    #[test]
+    fn test_calendar() {
+        let time = RtcClock::parse_datetime("2004-02-29T13:45:30").unwrap();
+        let date = DateTime::from_timestamp(time);
+        assert_eq!((date.year, date.month, date.day), (2004, 2, 29));
+        assert_eq!((date.hour, date.minute, date.second), (13, 45, 30));
+        assert_eq!(date.weekday, 0);
+        assert_eq!(date.timestamp(), time);
+        assert_eq!(DateTime::from_timestamp(0).weekday, 4);
+        assert!(RtcClock::parse_datetime("2004-13-01T00:00:00").is_err());
+        assert!(RtcClock::parse_datetime("yesterday").is_err());
+    }
+
+    #[test]
+    fn test_read_and_write() {
+        let time = RtcClock::parse_datetime("2004-02-29T13:45:30").unwrap();
+        let mut rtc = Rtc::new(RtcClock::Fixed(time));
+
+        start(&mut rtc, 0x65);
+        let data: Vec<u8> = (0..7).map(|_| receive_byte(&mut rtc)).collect();
+        assert_eq!(data, vec![0x04, 0x02, 0x29, 0x00, 0x13, 0x45, 0x30]);
+
+        // 12-hour mode, then read the time with the command byte in reverse bit order
+        start(&mut rtc, 0x62);
+        send_byte_lsb_first(&mut rtc, 0x00);
+        rtc.write_pins(0);
+        start(&mut rtc, 0xe6);
+        let data: Vec<u8> = (0..3).map(|_| receive_byte(&mut rtc)).collect();
+        assert_eq!(data, vec![0x41, 0x45, 0x30]);
+
+        // Set the date and time
+        rtc.write_pins(0);
+        start(&mut rtc, 0x64);
+        for byte in [0x10, 0x12, 0x31, 0x05, 0x51, 0x59, 0x58] {
+            send_byte_lsb_first(&mut rtc, byte);
+        }
+        assert_eq!(
+            rtc.clock,
+            RtcClock::Fixed(RtcClock::parse_datetime("2010-12-31T23:59:58").unwrap())
+        );
+
+        // Status and interrupts
+        rtc.write_pins(0);
+        start(&mut rtc, 0x62);
+        send_byte_lsb_first(&mut rtc, 0xff);
+        rtc.write_pins(0);
+        start(&mut rtc, 0x63);
+        assert_eq!(receive_byte(&mut rtc), 0x6a);
+        assert!(!rtc.poll_irq());
+        rtc.write_pins(0);
+        start(&mut rtc, 0x68);
+        assert!(rtc.poll_irq());
+        assert!(!rtc.poll_irq());
+    }
+}
@@ -0,0 +1,261 @@
+use crate::common::BitOperation;
+
+/// Raw value of the tilt sensor when the gba is horizontal
+pub const TILT_CENTER: u16 = 0x3a0;
+/// Largest difference from the center reported by the tilt sensor
+pub const TILT_RANGE: u16 = 0x100;
+/// Raw value of the gyro sensor when the gba is not rotating
+pub const GYRO_CENTER: u16 = 0x6c0;
+/// Largest difference from the center reported by the gyro sensor
+pub const GYRO_RANGE: u16 = 0x300;
+
+/// sensors::SolarSensor
+///
+/// Light sensor of the Boktai game paks, on the gpio port: pin 0 clocks a counter, pin 1 resets
+/// it and pin 2 must be low to select the sensor. Pin 3 is set once the counter reaches a value
+/// which is lower in bright light, so that the game measures the light by counting the clock
+/// pulses until the flag is set.
+pub struct SolarSensor {
+    pub light_level: u8, // From 0 (dark) to 255 (bright sunlight)
+    counter: u8,
+    pins: u32,
+}
+
+impl SolarSensor {
+    pub fn new() -> Self {
+        Self {
+            light_level: 0,
+            counter: 0,
+            pins: 0,
+        }
+    }
+
+    /// SolarSensor::read_pins
+    ///
+    /// @return [u32]: value driven by the sensor on the pins of the port
+    pub fn read_pins(&self) -> u32 {
+        ((self.counter >= 0xff - self.light_level) as u32) << 3
+    }
+
+    /// SolarSensor::write_pins
+    ///
+    /// @param pins [u32]: value of the pins of the port
+    pub fn write_pins(&mut self, pins: u32) {
+        let previous = self.pins;
+        self.pins = pins;
+
+        if pins.is_bit_set(2) {
+            return;
+        }
+
+        if pins.is_bit_set(1) {
+            self.counter = 0;
+        } else if previous.is_bit_clear(0) && pins.is_bit_set(0) {
+            self.counter = self.counter.saturating_add(1);
+        }
+    }
+}
+
+/// sensors::GyroSensor
+///
+/// Rotation sensor of WarioWare: Twisted!, on the gpio port: a high level on pin 0 samples the
+/// rotation speed, which is then shifted out on pin 2, most significant bit first, at each falling
+/// edge of the clock on pin 1. The 16 bits of the sample contain a 12 bits value.
+pub struct GyroSensor {
+    pub rotation: u16,
+    sample: u16,
+    pins: u32,
+    output: u32,
+}
+
+impl GyroSensor {
+    pub fn new() -> Self {
+        Self {
+            rotation: GYRO_CENTER,
+            sample: 0,
+            pins: 0,
+            output: 0,
+        }
+    }
+
+    /// GyroSensor::read_pins
+    ///
+    /// @return [u32]: value driven by the sensor on the pins of the port
+    pub fn read_pins(&self) -> u32 {
+        self.output << 2
+    }
+
+    /// GyroSensor::write_pins
+    ///
+    /// @param pins [u32]: value of the pins of the port
+    pub fn write_pins(&mut self, pins: u32) {
+        let previous = self.pins;
+        self.pins = pins;
+
+        if pins.is_bit_set(0) {
+            self.sample = self.rotation & 0x0fff;
+            self.output = 0;
+        } else if previous.is_bit_set(1) && pins.is_bit_clear(1) {
+            self.output = (self.sample >> 15) as u32;
+            self.sample <<= 1;
+        }
+    }
+}
+
+/// sensors::Rumble
+///
+/// Vibration motor of some game paks, driven by pin 3 of the gpio port.
+pub struct Rumble {
+    pub active: bool,
+}
+
+impl Rumble {
+    pub fn new() -> Self {
+        Self { active: false }
+    }
+
+    /// Rumble::write_pins
+    ///
+    /// @param pins [u32]: value of the pins of the port
+    pub fn write_pins(&mut self, pins: u32) {
+        self.active = pins.is_bit_set(3);
+    }
+}
+
+/// sensors::TiltSensor
+///
+/// 2 axes accelerometer of Yoshi Topsy-Turvy and Koro Koro Puzzle, mapped at 0x0e008000 -
+/// 0x0e0085ff on the 8 bits bus of the backup region:
+///
+/// Address     Content
+/// -------------------------------------------------------
+/// 0x0e008000  Write 0x55, then 0xaa at 0x0e008100 to sample
+/// 0x0e008200  Bits 0-7 of the x axis
+/// 0x0e008300  Bits 8-11 of the x axis, bit 7 set when ready
+/// 0x0e008400  Bits 0-7 of the y axis
+/// 0x0e008500  Bits 8-11 of the y axis
+/// -------------------------------------------------------
+pub struct TiltSensor {
+    pub x: u16,
+    pub y: u16,
+    sample: (u16, u16),
+    unlocked: bool,
+    ready: bool,
+}
+
+impl TiltSensor {
+    pub fn new() -> Self {
+        Self {
+            x: TILT_CENTER,
+            y: TILT_CENTER,
+            sample: (TILT_CENTER, TILT_CENTER),
+            unlocked: false,
+            ready: false,
+        }
+    }
+
+    /// TiltSensor::is_tilt_address
+    ///
+    /// @param address [u32]: address of the access, in the first copy of the backup region
+    /// @return [bool]: true if the access is directed to the sensor
+    pub fn is_tilt_address(address: u32) -> bool {
+        (0x0e008000..=0x0e0085ff).contains(&address)
+    }
+
+    /// TiltSensor::read
+    ///
+    /// @param address [u32]: address of the access
+    /// @return [u32]: register, repeated on the 4 lanes of the bus
+    pub fn read(&self, address: u32) -> u32 {
+        let (x, y) = self.sample;
+        let value = match address & 0xff00 {
+            0x8200 => x & 0xff,
+            0x8300 => (x >> 8) & 0xf | if self.ready { 0x80 } else { 0 },
+            0x8400 => y & 0xff,
+            0x8500 => (y >> 8) & 0xf,
+            _ => 0,
+        };
+        value as u32 * 0x01010101
+    }
+
+    /// TiltSensor::write
+    ///
+    /// @param address [u32]: address of the access
+    /// @param data [u32]: data on the bus
+    pub fn write(&mut self, address: u32, data: u32) {
+        let byte = (data >> ((address & 3) * 8)) & 0xff;
+        match (address & 0xff00, byte) {
+            (0x8000, 0x55) => self.unlocked = true,
+            (0x8100, 0xaa) if self.unlocked => {
+                self.sample = (self.x & 0x0fff, self.y & 0x0fff);
+                self.unlocked = false;
+                self.ready = true;
+            }
+            _ => self.unlocked = false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_sensors {
+
+    use crate::cartridge::sensors::{GyroSensor, Rumble, SolarSensor, TiltSensor};
+
+    #[test]
+    fn test_solar_sensor() {
+        let mut solar = SolarSensor::new();
+        solar.light_level = 0xf0;
+
+        // Count the clock pulses until the flag is set
+        solar.write_pins(0b0010);
+        solar.write_pins(0b0000);
+        let pulses = (1..=0xff)
+            .find(|_| {
+                solar.write_pins(0b0001);
+                solar.write_pins(0b0000);
+                solar.read_pins() == 0b1000
+            })
+            .unwrap();
+        assert_eq!(pulses, 0x0f);
+
+        // Ignored while the rtc is selected
+        solar.write_pins(0b0110);
+        assert_eq!(solar.read_pins(), 0b1000);
+    }
+
+    #[test]
+    fn test_gyro_sensor() {
+        let mut gyro = GyroSensor::new();
+        gyro.rotation = 0x0abc;
+
+        gyro.write_pins(0b0001);
+        gyro.write_pins(0b0000);
+        let sample = (0..16).fold(0, |sample, _| {
+            gyro.write_pins(0b0010);
+            gyro.write_pins(0b0000);
+            (sample << 1) | (gyro.read_pins() >> 2)
+        });
+        assert_eq!(sample, 0x0abc);
+
+        let mut rumble = Rumble::new();
+        rumble.write_pins(0b1000);
+        assert!(rumble.active);
+    }
+
+    #[test]
+    fn test_tilt_sensor() {
+        let mut tilt = TiltSensor::new();
+        tilt.x = 0x123;
+        tilt.y = 0x456;
+        assert_eq!(tilt.read(0x0e008300), 0x03030303);
+
+        tilt.write(0x0e008000, 0x55555555);
+        tilt.write(0x0e008100, 0xaaaaaaaa);
+        assert_eq!(tilt.read(0x0e008200), 0x23232323);
+        assert_eq!(tilt.read(0x0e008300) & 0xff, 0x81);
+        assert_eq!(tilt.read(0x0e008400) & 0xff, 0x56);
+        assert_eq!(tilt.read(0x0e008500) & 0xff, 0x04);
+        assert!(TiltSensor::is_tilt_address(0x0e008500));
+        assert!(!TiltSensor::is_tilt_address(0x0e000000));
+    }
+}
@@ -1,4 +1,4 @@
-use sdl2::pixels::{Color, PixelFormat, PixelFormatEnum};
+use sdl2::pixels::{Color, PixelFormatEnum};
 use sdl2::render::Canvas;
 use sdl2::video::Window;
 
@@ -54,13 +54,4 @@ impl Display {
 
         self.canvas.present();
     }
-
-    pub fn clear(&mut self, color: u32) {
-        self.canvas.set_draw_color(Color::from_u32(
-            &PixelFormat::try_from(PixelFormatEnum::RGBA8888).unwrap(),
-            color,
-        ));
-        self.canvas.clear();
-        self.canvas.present();
-    }
 }
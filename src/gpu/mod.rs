@@ -4,17 +4,18 @@ pub mod utils;
 use crate::bus::TransferSize;
 use crate::common::BitOperation;
 use crate::gpu::display::Display;
+use crate::io::registers::{IoRegisters, DISPCNT, DISPSTAT, VCOUNT};
 use crate::memory::Memory;
 
 pub struct Gpu {
     pub vram: Memory,
     pub palette_ram: Memory,
     pub oam: Memory,
-    pub gpu_registers: Memory,
     h_counter: u32,
     v_counter: u32,
     dot_counter: u32,
-    display: Display,
+    display: Option<Display>,
+    pub headless: bool,
     display_array: Vec<u8>,
     current_dispcnt: u32,
 }
@@ -24,14 +25,12 @@ pub const H_SIZE: u32 = 240;
 
 impl Gpu {
     pub fn new() -> Self {
-        let mut display = Display::new();
-        display.clear(0xffffffff);
         Self {
             vram: Memory::new(0x06000000, 0x18000, false, String::from("VRAM")),
             palette_ram: Memory::new(0x05000000, 0x400, false, String::from("PALETTE RAM")),
             oam: Memory::new(0x07000000, 0x400, false, String::from("OAM")),
-            gpu_registers: Memory::new(0x04000000, 0x58, false, String::from("GPU REGISTERS")),
-            display,
+            display: None,
+            headless: false,
             h_counter: 0,
             v_counter: 0,
             dot_counter: 0,
@@ -44,8 +43,24 @@ impl Gpu {
         }
     }
 
-    pub fn step(&mut self) {
-        let mut dispstat = self.gpu_registers.read_halfword(0x04000004);
+    /// Gpu::reset
+    ///
+    /// Restart the frame from the first pixel and reset the video memories.
+    ///
+    /// @param seed [Option<u64>]: seed used to fill the memories with random content, if any
+    pub fn reset(&mut self, seed: Option<u64>) {
+        self.vram.reset(seed);
+        self.palette_ram.reset(seed);
+        self.oam.reset(seed);
+        self.h_counter = 0;
+        self.v_counter = 0;
+        self.dot_counter = 0;
+        self.current_dispcnt = 0;
+    }
+
+    pub fn step(&mut self, io: &mut IoRegisters) {
+        let mut dispstat = io.get(DISPSTAT);
+        self.current_dispcnt = io.get(DISPCNT);
 
         self.dot_counter += 1;
 
@@ -56,8 +71,6 @@ impl Gpu {
         self.dot_counter = 0;
 
         if self.v_counter < V_SIZE && self.h_counter < H_SIZE {
-            self.current_dispcnt = self.gpu_registers.read_halfword(0x04000000);
-
             if self.current_dispcnt.get_range(2, 0) == 3 {
                 self.gpu_mode_3();
             } else if self.current_dispcnt.get_range(2, 0) == 4 {
@@ -76,7 +89,12 @@ impl Gpu {
 
         if self.v_counter == V_SIZE + 68 {
             self.v_counter = 0;
-            self.display.update(&self.display_array);
+            // The window is opened when the first frame is complete
+            if !self.headless {
+                self.display
+                    .get_or_insert_with(Display::new)
+                    .update(&self.display_array);
+            }
         }
 
         if self.v_counter >= 160 && self.v_counter < 227 {
@@ -91,10 +109,8 @@ impl Gpu {
             dispstat = dispstat.clear_bit(1);
         }
 
-        self.gpu_registers
-            .write(0x04000006, self.v_counter << 16, TransferSize::HALFWORD);
-        self.gpu_registers
-            .write(0x04000004, dispstat, TransferSize::HALFWORD);
+        io.set(VCOUNT, self.v_counter);
+        io.set(DISPSTAT, dispstat);
     }
 
     pub fn read(&self, address: u32, mas: TransferSize) -> u32 {
@@ -104,23 +120,15 @@ impl Gpu {
             return self.palette_ram.read(address, mas);
         } else if address >= 0x07000000 && address < 0x07000400 {
             return self.oam.read(address, mas);
-        } else if address >= 0x04000000 && address < 0x04000058 {
-            return self.gpu_registers.read(address, mas);
         } else {
             todo!();
         }
     }
 
-    pub fn write(&mut self, address: u32, data: u32, mut mas: TransferSize) {
-        if address >= 0x04000000 && address < 0x04000058 {
-            self.gpu_registers.write(address, data, mas);
-            return;
-        }
-
-        // VRAM, PRAM and OAM do not allow byte wrinting. When you try to do it,
-        // they automatically become halfword writing
+    pub fn write(&mut self, address: u32, data: u32, mas: TransferSize, io: &IoRegisters) {
         if mas == TransferSize::BYTE {
-            mas = TransferSize::HALFWORD;
+            self.write_byte(address, data, io.get(DISPCNT));
+            return;
         }
 
         if address >= 0x06000000 && address < 0x06018000 {
@@ -133,4 +141,88 @@ impl Gpu {
             todo!();
         }
     }
+
+    /// Gpu::write_byte
+    ///
+    /// VRAM, PRAM and OAM have a 16 bits data bus. A byte write to BG VRAM or PRAM writes the byte
+    /// in both halves of the addressed halfword, while byte writes to OBJ VRAM and OAM are
+    /// ignored.
+    ///
+    /// @param address [u32]: address to write
+    /// @param data [u32]: data on the bus
+    /// @param dispcnt [u32]: current value of DISPCNT
+    fn write_byte(&mut self, address: u32, data: u32, dispcnt: u32) {
+        if Self::is_byte_write_ignored(address, dispcnt) {
+            return;
+        }
+
+        let byte = (data >> ((address & 3) * 8)) & 0xff;
+        let halfword = (byte | (byte << 8)) as u16;
+
+        match address {
+            0x06000000..=0x06017fff => self.vram.write16(address, halfword),
+            0x05000000..=0x050003ff => self.palette_ram.write16(address, halfword),
+            _ => {}
+        }
+    }
+
+    /// Gpu::is_byte_write_ignored
+    ///
+    /// OBJ VRAM starts at 0x06010000 in tile modes (0-2) and at 0x06014000 in bitmap modes (3-5),
+    /// where the first part of the region is used by the frame buffers.
+    ///
+    /// @param address [u32]: address of the byte write
+    /// @param dispcnt [u32]: current value of DISPCNT
+    /// @return [bool]: true if the write targets OBJ VRAM or OAM
+    fn is_byte_write_ignored(address: u32, dispcnt: u32) -> bool {
+        let obj_vram_start = if dispcnt.get_range(2, 0) >= 3 {
+            0x06014000
+        } else {
+            0x06010000
+        };
+
+        (obj_vram_start..0x06018000).contains(&address)
+            || (0x07000000..0x07000400).contains(&address)
+    }
+}
+
+#[cfg(test)]
+mod test_gpu {
+
+    use crate::bus::TransferSize;
+    use crate::gpu::Gpu;
+    use crate::io::registers::{IoRegisters, DISPCNT};
+
+    #[test]
+    fn test_is_byte_write_ignored() {
+        // Tile mode
+        assert!(!Gpu::is_byte_write_ignored(0x0600ffff, 0));
+        assert!(Gpu::is_byte_write_ignored(0x06010000, 0));
+        assert!(Gpu::is_byte_write_ignored(0x06014000, 2));
+
+        // Bitmap mode
+        assert!(!Gpu::is_byte_write_ignored(0x06010000, 3));
+        assert!(!Gpu::is_byte_write_ignored(0x06013fff, 4));
+        assert!(Gpu::is_byte_write_ignored(0x06014000, 5));
+
+        // PRAM and OAM
+        assert!(!Gpu::is_byte_write_ignored(0x05000001, 0));
+        assert!(Gpu::is_byte_write_ignored(0x07000001, 0));
+    }
+
+    #[test]
+    fn test_byte_write_follows_dispcnt() {
+        let mut gpu = Gpu::new();
+        let mut io = IoRegisters::new();
+
+        // The mode is the one of DISPCNT when the write happens
+        gpu.write(0x06010001, 0x0000ab00, TransferSize::BYTE, &io);
+        assert_eq!(gpu.read(0x06010000, TransferSize::HALFWORD) & 0xffff, 0);
+        io.set(DISPCNT, 3);
+        gpu.write(0x06010001, 0x0000ab00, TransferSize::BYTE, &io);
+        assert_eq!(
+            gpu.read(0x06010000, TransferSize::HALFWORD) & 0xffff,
+            0xabab
+        );
+    }
 }
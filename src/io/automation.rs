@@ -0,0 +1,241 @@
+use crate::io::keypad::Button;
+use sdl2::keyboard::Keycode;
+
+/// Default number of frames between two toggles of a turbo button
+pub const DEFAULT_TURBO_RATE: u32 = 2;
+
+/// automation::TurboButton
+///
+/// Autofire variant of a button: while `key` is held, `button` is toggled every `rate` frames.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct TurboButton {
+    pub button: Button,
+    pub key: Keycode,
+    pub rate: u32,
+    held_frames: u32,
+}
+
+/// automation::ButtonMacro
+///
+/// Named sequence of button states which is played back one frame at a time when `hotkey` is
+/// pressed. Each element of `frames` is a mask of the pressed buttons (bit set = pressed), using
+/// the same bit positions as KEYINPUT.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ButtonMacro {
+    pub name: String,
+    pub hotkey: Keycode,
+    pub frames: Vec<u16>,
+}
+
+/// automation::InputAutomation
+///
+/// Turbo buttons and macros applied by the keypad on top of the buttons pressed by the user, so
+/// that the game only sees the resulting KEYINPUT value.
+pub struct InputAutomation {
+    turbo_buttons: Vec<TurboButton>,
+    macros: Vec<ButtonMacro>,
+    playback: Option<(usize, usize)>, // (index of the macro, next frame to play)
+    recording: Option<Vec<u16>>,
+}
+
+impl InputAutomation {
+    /// InputAutomation::new
+    ///
+    /// Create the automation layer with turbo A/B/L/R mapped to U/I/E/O and no macros.
+    pub fn new() -> Self {
+        Self {
+            turbo_buttons: vec![
+                TurboButton::new(Button::A, Keycode::U, DEFAULT_TURBO_RATE),
+                TurboButton::new(Button::B, Keycode::I, DEFAULT_TURBO_RATE),
+                TurboButton::new(Button::L, Keycode::E, DEFAULT_TURBO_RATE),
+                TurboButton::new(Button::R, Keycode::O, DEFAULT_TURBO_RATE),
+            ],
+            macros: Vec::new(),
+            playback: None,
+            recording: None,
+        }
+    }
+
+    /// InputAutomation::set_turbo_rate
+    ///
+    /// Modify the autofire rate of all the turbo variants of a button.
+    ///
+    /// @param button [Button]: button to modify
+    /// @param rate [u32]: frames per toggle (values smaller than 1 are treated as 1)
+    pub fn set_turbo_rate(&mut self, button: Button, rate: u32) {
+        for turbo in self.turbo_buttons.iter_mut() {
+            if turbo.button == button {
+                turbo.rate = rate.max(1);
+            }
+        }
+    }
+
+    /// InputAutomation::add_macro
+    ///
+    /// Register a macro. A previous macro with the same name is replaced.
+    ///
+    /// @param button_macro [ButtonMacro]: macro to add
+    pub fn add_macro(&mut self, button_macro: ButtonMacro) {
+        self.playback = None;
+        self.macros.retain(|m| m.name != button_macro.name);
+        self.macros.push(button_macro);
+    }
+
+    /// InputAutomation::trigger
+    ///
+    /// Start the playback of the macro associated to a hotkey, if any.
+    ///
+    /// @param hotkey [Keycode]: key which was pressed
+    /// @return [bool]: true if a macro was started
+    pub fn trigger(&mut self, hotkey: Keycode) -> bool {
+        match self.macros.iter().position(|m| m.hotkey == hotkey) {
+            Some(index) => {
+                self.playback = Some((index, 0));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// InputAutomation::is_recording
+    ///
+    /// @return [bool]: true if the button states are currently being recorded
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// InputAutomation::start_recording
+    ///
+    /// Start recording the button states seen by the game, one element per frame.
+    pub fn start_recording(&mut self) {
+        self.recording = Some(Vec::new());
+    }
+
+    /// InputAutomation::stop_recording
+    ///
+    /// Stop the current recording and store it as a macro.
+    ///
+    /// @param name [String]: name of the new macro
+    /// @param hotkey [Keycode]: key to play the new macro
+    pub fn stop_recording(&mut self, name: String, hotkey: Keycode) {
+        if let Some(frames) = self.recording.take() {
+            self.add_macro(ButtonMacro {
+                name,
+                hotkey,
+                frames,
+            });
+        }
+    }
+
+    /// InputAutomation::apply
+    ///
+    /// Compute the buttons seen by the game in the current frame, and move to the next frame.
+    ///
+    /// @param pressed [u16]: mask of the buttons pressed by the user
+    /// @param is_key_held [Fn(Keycode) -> bool]: whether a key is currently held
+    /// @return [u16]: mask of the buttons to be reported to the game
+    pub fn apply<F: Fn(Keycode) -> bool>(&mut self, pressed: u16, is_key_held: F) -> u16 {
+        let mut pressed = pressed;
+
+        // While a turbo key is held, the button is pressed for `rate` frames and released for
+        // `rate` frames
+        for turbo in self.turbo_buttons.iter_mut() {
+            if is_key_held(turbo.key) {
+                if (turbo.held_frames / turbo.rate) % 2 == 0 {
+                    pressed |= 1 << turbo.button as u16;
+                }
+                turbo.held_frames = turbo.held_frames.wrapping_add(1);
+            } else {
+                turbo.held_frames = 0;
+            }
+        }
+
+        if let Some((index, frame)) = self.playback {
+            let frames = &self.macros[index].frames;
+            if frame < frames.len() {
+                pressed |= frames[frame];
+            }
+            self.playback = if frame + 1 < frames.len() {
+                Some((index, frame + 1))
+            } else {
+                None
+            };
+        }
+
+        if let Some(recording) = self.recording.as_mut() {
+            recording.push(pressed);
+        }
+
+        pressed
+    }
+}
+
+impl TurboButton {
+    /// TurboButton::new
+    ///
+    /// @param button [Button]: button to press
+    /// @param key [Keycode]: key to hold
+    /// @param rate [u32]: frames per toggle
+    pub fn new(button: Button, key: Keycode, rate: u32) -> Self {
+        Self {
+            button,
+            key,
+            rate: rate.max(1),
+            held_frames: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_automation {
+
+    use crate::io::automation::{ButtonMacro, InputAutomation};
+    use crate::io::keypad::Button;
+    use sdl2::keyboard::Keycode;
+
+    #[test]
+    fn test_turbo() {
+        let mut automation = InputAutomation::new();
+        automation.set_turbo_rate("a".parse().unwrap(), 2);
+        assert!("x".parse::<Button>().is_err());
+
+        let held = |key| key == Keycode::U;
+        let frames: Vec<u16> = (0..6).map(|_| automation.apply(0, held)).collect();
+        assert_eq!(frames, vec![1, 1, 0, 0, 1, 1]);
+
+        // Releasing the key restarts the sequence
+        assert_eq!(automation.apply(0, |_| false), 0);
+        assert_eq!(automation.apply(0, held), 1);
+    }
+
+    #[test]
+    fn test_macro_record_and_play() {
+        let mut automation = InputAutomation::new();
+
+        automation.start_recording();
+        automation.apply(1 << Button::A as u16, |_| false);
+        automation.apply(0, |_| false);
+        automation.apply(1 << Button::UP as u16, |_| false);
+        automation.stop_recording(String::from("test"), Keycode::F2);
+        assert!(!automation.is_recording());
+
+        assert!(!automation.trigger(Keycode::F3));
+        assert!(automation.trigger(Keycode::F2));
+        assert_eq!(automation.apply(0, |_| false), 1 << Button::A as u16);
+        assert_eq!(automation.apply(0, |_| false), 0);
+        assert_eq!(
+            automation.apply(1 << Button::B as u16, |_| false),
+            (1 << Button::UP as u16) | (1 << Button::B as u16)
+        );
+        assert_eq!(automation.apply(0, |_| false), 0);
+
+        automation.add_macro(ButtonMacro {
+            name: String::from("test"),
+            hotkey: Keycode::F4,
+            frames: vec![0x3ff],
+        });
+        assert!(!automation.trigger(Keycode::F2));
+        assert!(automation.trigger(Keycode::F4));
+        assert_eq!(automation.apply(0, |_| false), 0x3ff);
+    }
+}
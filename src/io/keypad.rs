@@ -1,100 +1,264 @@
-use crate::bus::TransferSize;
+use crate::apu::audio_output::AudioCommand;
 use crate::common::BitOperation;
-use crate::memory::Memory;
+use crate::io::automation::InputAutomation;
+use crate::io::registers::{IoRegisters, KEYINPUT};
+use crate::io::sensor_input::{SensorChanges, SensorInput, Stick};
+use sdl2::controller::{Axis, GameController};
 use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
+use sdl2::keyboard::{Keycode, Scancode};
 use sdl2::Sdl;
+use std::str::FromStr;
+
+/// Key used to start and stop the recording of a macro
+const RECORD_MACRO_KEY: Keycode = Keycode::F1;
+/// Key used to play the last recorded macro
+const PLAY_RECORDED_MACRO_KEY: Keycode = Keycode::F2;
+/// Duration of the rumble effect of the game controller, longer than the interval between steps
+const RUMBLE_DURATION_MS: u32 = 100;
+
+/// keypad::Button
+///
+/// enum to represent the buttons of the gba, using as value their bit in KEYINPUT
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[repr(u16)]
+pub enum Button {
+    A = 0,
+    B = 1,
+    SELECT = 2,
+    START = 3,
+    RIGHT = 4,
+    LEFT = 5,
+    UP = 6,
+    DOWN = 7,
+    R = 8,
+    L = 9,
+}
+
+impl FromStr for Button {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "a" => Ok(Button::A),
+            "b" => Ok(Button::B),
+            "select" => Ok(Button::SELECT),
+            "start" => Ok(Button::START),
+            "right" => Ok(Button::RIGHT),
+            "left" => Ok(Button::LEFT),
+            "up" => Ok(Button::UP),
+            "down" => Ok(Button::DOWN),
+            "r" => Ok(Button::R),
+            "l" => Ok(Button::L),
+            _ => Err(format!("unknown button {}", name)),
+        }
+    }
+}
 
 pub struct Keypad {
-    pub keypad_registers: Memory,
-    sdl_context: Sdl,
+    pub automation: InputAutomation,
+    pub sensor_input: SensorInput,
+    sensor_changes: SensorChanges,
+    audio_commands: Vec<AudioCommand>,
+    controller: Option<GameController>,
+    sdl_context: Option<Sdl>,
 }
 
 impl Keypad {
     pub fn new() -> Self {
-        let sdl_context = sdl2::init().unwrap();
         Self {
-            keypad_registers: Memory::new(0x04000130, 0x4, false, String::from("KEYPAD REGISTERS")),
-            sdl_context,
+            automation: InputAutomation::new(),
+            sensor_input: SensorInput::new(),
+            sensor_changes: SensorChanges::default(),
+            audio_commands: Vec::new(),
+            controller: None,
+            sdl_context: None,
         }
     }
 
-    pub fn step(&mut self) {
-        let keycnt = self.keypad_registers.read_halfword(0x04000132);
-        let mut keyinput = 0xffff;
+    /// Keypad::step
+    ///
+    /// Handle the events of the window, and update KEYINPUT with the buttons pressed.
+    ///
+    /// @param io [&mut IoRegisters]: I/O registers
+    /// @return [bool]: true if the user asked to close the emulator
+    pub fn step(&mut self, io: &mut IoRegisters) -> bool {
+        let mut pressed: u16 = 0;
+        let mut quit = false;
 
-        let mut events = self.sdl_context.event_pump().unwrap();
+        let mut events = self.sdl_context().event_pump().unwrap();
 
         for event in events.poll_iter() {
             match event {
-                Event::Quit { .. } => std::process::exit(0),
-                Event::KeyDown {
+                Event::Quit { .. }
+                | Event::KeyDown {
                     keycode: Some(Keycode::Backspace),
                     ..
-                } => std::process::exit(1),
+                } => quit = true,
                 Event::KeyDown {
                     keycode: Some(Keycode::A),
                     ..
-                } => keyinput = keyinput.clear_bit(5),
+                } => pressed = pressed.set_bit(Button::LEFT as u16),
                 Event::KeyDown {
                     keycode: Some(Keycode::S),
                     ..
-                } => keyinput = keyinput.clear_bit(7),
+                } => pressed = pressed.set_bit(Button::DOWN as u16),
                 Event::KeyDown {
                     keycode: Some(Keycode::D),
                     ..
-                } => keyinput = keyinput.clear_bit(4),
+                } => pressed = pressed.set_bit(Button::RIGHT as u16),
                 Event::KeyDown {
                     keycode: Some(Keycode::W),
                     ..
-                } => keyinput = keyinput.clear_bit(6),
+                } => pressed = pressed.set_bit(Button::UP as u16),
                 Event::KeyDown {
                     keycode: Some(Keycode::J),
                     ..
-                } => keyinput = keyinput.clear_bit(0),
+                } => pressed = pressed.set_bit(Button::A as u16),
                 Event::KeyDown {
                     keycode: Some(Keycode::K),
                     ..
-                } => keyinput = keyinput.clear_bit(1),
+                } => pressed = pressed.set_bit(Button::B as u16),
                 Event::KeyDown {
                     keycode: Some(Keycode::V),
                     ..
-                } => keyinput = keyinput.clear_bit(3),
+                } => pressed = pressed.set_bit(Button::START as u16),
                 Event::KeyDown {
                     keycode: Some(Keycode::B),
                     ..
-                } => keyinput = keyinput.clear_bit(2),
+                } => pressed = pressed.set_bit(Button::SELECT as u16),
                 Event::KeyDown {
                     keycode: Some(Keycode::Q),
                     ..
-                } => keyinput = keyinput.clear_bit(9),
+                } => pressed = pressed.set_bit(Button::L as u16),
                 Event::KeyDown {
                     keycode: Some(Keycode::P),
                     ..
-                } => keyinput = keyinput.clear_bit(8),
+                } => pressed = pressed.set_bit(Button::R as u16),
+
+                // Start recording a macro, or store the current recording so that it can be
+                // played back using PLAY_RECORDED_MACRO_KEY
+                Event::KeyDown {
+                    keycode: Some(RECORD_MACRO_KEY),
+                    repeat: false,
+                    ..
+                } => {
+                    if self.automation.is_recording() {
+                        self.automation
+                            .stop_recording(String::from("recorded"), PLAY_RECORDED_MACRO_KEY);
+                    } else {
+                        self.automation.start_recording();
+                    }
+                }
+
+                // Master volume and mute of the audio output
+                Event::KeyDown {
+                    keycode: Some(Keycode::Minus),
+                    ..
+                } => self.audio_commands.push(AudioCommand::VolumeDown),
+                Event::KeyDown {
+                    keycode: Some(Keycode::Equals),
+                    ..
+                } => self.audio_commands.push(AudioCommand::VolumeUp),
+                Event::KeyDown {
+                    keycode: Some(Keycode::M),
+                    repeat: false,
+                    ..
+                } => self.audio_commands.push(AudioCommand::ToggleMute),
+
+                // Any other key might be the hotkey of a macro
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    repeat: false,
+                    ..
+                } => {
+                    self.automation.trigger(keycode);
+                }
                 _ => {}
             }
         }
 
-        self.keypad_registers
-            .write(0x04000130, keyinput, TransferSize::HALFWORD);
-        self.keypad_registers
-            .write(0x04000132, keycnt << 16, TransferSize::HALFWORD);
+        // Turbo buttons are active as long as their key is held
+        let keyboard = events.keyboard_state();
+        let pressed = self.automation.apply(pressed, |keycode| {
+            Scancode::from_keycode(keycode)
+                .map(|scancode| keyboard.is_scancode_pressed(scancode))
+                .unwrap_or(false)
+        });
+
+        io.set(KEYINPUT, 0x03ff & !(pressed as u32));
+
+        let stick = self.stick_position();
+        self.sensor_changes = self.sensor_input.update(
+            |keycode| {
+                Scancode::from_keycode(keycode)
+                    .map(|scancode| keyboard.is_scancode_pressed(scancode))
+                    .unwrap_or(false)
+            },
+            stick,
+        );
+        quit
     }
 
-    pub fn read(&self, address: u32, mas: TransferSize) -> u32 {
-        if address >= 0x04000130 && address < 0x04000134 {
-            return self.keypad_registers.read(address, mas);
-        } else {
-            todo!();
+    /// Keypad::take_sensor_changes
+    ///
+    /// @return [SensorChanges]: changes of the sensors of the game pak requested by the user
+    /// during the last step
+    pub fn take_sensor_changes(&mut self) -> SensorChanges {
+        std::mem::take(&mut self.sensor_changes)
+    }
+
+    /// Keypad::take_audio_commands
+    ///
+    /// @return [Vec<AudioCommand>]: changes of the audio output requested by the user during the
+    /// last step
+    pub fn take_audio_commands(&mut self) -> Vec<AudioCommand> {
+        std::mem::take(&mut self.audio_commands)
+    }
+
+    /// Keypad::set_rumble
+    ///
+    /// Forward the state of the rumble motor of the game pak to the game controller, if one was
+    /// opened for the sensors. The effect is renewed at each step while the motor is active.
+    ///
+    /// @param active [bool]: true if the rumble motor is active
+    pub fn set_rumble(&mut self, active: bool) {
+        if let Some(controller) = self.controller.as_mut() {
+            let strength = if active { 0xffff } else { 0 };
+            let _ = controller.set_rumble(strength, strength, RUMBLE_DURATION_MS);
         }
     }
 
-    pub fn write(&mut self, address: u32, data: u32, mas: TransferSize) {
-        if address >= 0x04000132 && address < 0x04000134 {
-            self.keypad_registers.write(address, data, mas);
-        } else {
-            todo!();
+    /// Keypad::sdl_context
+    ///
+    /// SDL is initialized the first time the events are needed, so that the emulator can be built
+    /// without a display.
+    ///
+    /// @return [&Sdl]: context of SDL
+    fn sdl_context(&mut self) -> &Sdl {
+        self.sdl_context
+            .get_or_insert_with(|| sdl2::init().unwrap())
+    }
+
+    /// Keypad::stick_position
+    ///
+    /// The first game controller is opened the first time the stick is needed.
+    ///
+    /// @return [Option<(i16, i16)>]: position of the stick bound to the sensors, if any
+    fn stick_position(&mut self) -> Option<(i16, i16)> {
+        let stick = self.sensor_input.stick?;
+
+        if self.controller.is_none() {
+            let subsystem = self.sdl_context().game_controller().ok()?;
+            let index = (0..subsystem.num_joysticks().ok()?)
+                .find(|index| subsystem.is_game_controller(*index))?;
+            self.controller = subsystem.open(index).ok();
         }
+
+        let controller = self.controller.as_ref()?;
+        let (x_axis, y_axis) = match stick {
+            Stick::Left => (Axis::LeftX, Axis::LeftY),
+            Stick::Right => (Axis::RightX, Axis::RightY),
+        };
+        Some((controller.axis(x_axis), controller.axis(y_axis)))
     }
 }
@@ -1 +1,5 @@
+pub mod automation;
 pub mod keypad;
+pub mod registers;
+pub mod sensor_input;
+pub mod timers;
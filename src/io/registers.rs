@@ -0,0 +1,474 @@
+use crate::bus::TransferSize;
+use crate::memory::Memory;
+
+/// Address of the first I/O register
+pub const IO_INIT_ADDR: u32 = 0x04000000;
+/// Size of the region containing the I/O registers
+pub const IO_SIZE: u32 = 0x400;
+
+pub const DISPCNT: u32 = 0x04000000;
+pub const DISPSTAT: u32 = 0x04000004;
+pub const VCOUNT: u32 = 0x04000006;
+pub const KEYINPUT: u32 = 0x04000130;
+pub const IE: u32 = 0x04000200;
+pub const IF: u32 = 0x04000202;
+pub const WAITCNT: u32 = 0x04000204;
+
+/// registers::IoHook
+///
+/// Side effect of a write to an I/O register, handled by the bus once the register is updated.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum IoHook {
+    /// The waitstates configuration changed
+    WaitControl,
+    /// The bits written as 1 are cleared from IF
+    InterruptAcknowledge,
+    /// The control register of a DMA channel was written
+    DmaControl(usize),
+    /// A sound register, the wave RAM or a FIFO was written
+    Sound,
+    /// The reload value of a timer was written
+    TimerReload(usize),
+    /// The control register of a timer was written
+    TimerControl(usize),
+}
+
+/// registers::IoEvent
+///
+/// Write to a halfword of an I/O register having a hook.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct IoEvent {
+    pub hook: IoHook,
+    pub address: u32, // Address of the halfword
+    pub data: u32,    // Data written to the halfword
+    pub lanes: u32,   // Bits of the halfword involved in the transfer
+}
+
+/// registers::IoRegister
+///
+/// Descriptor of an I/O register. Bits which are not in `read_mask` are read as zero, while bits
+/// which are not in `write_mask` are not modified by the cpu. A register with an empty read mask
+/// is write-only, and reading it returns the open bus value. `reset_value` is the content of the
+/// register after the boot sequence.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct IoRegister {
+    pub name: &'static str,
+    pub address: u32,
+    pub size: u32,
+    pub read_mask: u32,
+    pub write_mask: u32,
+    pub reset_value: u32,
+    pub hook: Option<IoHook>,
+}
+
+impl IoRegister {
+    const fn new(
+        name: &'static str,
+        address: u32,
+        size: u32,
+        read_mask: u32,
+        write_mask: u32,
+    ) -> Self {
+        Self {
+            name,
+            address,
+            size,
+            read_mask,
+            write_mask,
+            reset_value: 0,
+            hook: None,
+        }
+    }
+
+    const fn with_reset(mut self, reset_value: u32) -> Self {
+        self.reset_value = reset_value;
+        self
+    }
+
+    const fn with_hook(mut self, hook: IoHook) -> Self {
+        self.hook = Some(hook);
+        self
+    }
+}
+
+/// Table of the I/O registers, from gbatek/gba-i-o-map. Registers are either 2 or 4 bytes long;
+/// unused halfwords of 32 bits registers are read as zero. Registers are zero after reset, apart
+/// from forced blank in DISPCNT, the identity matrices of BG2 and BG3, KEYINPUT (no button
+/// pressed), SOUNDBIAS (bias level 0x100), RCNT (general purpose mode) and POSTFLG (boot
+/// completed).
+#[rustfmt::skip]
+pub const IO_REGISTERS: &[IoRegister] = &[
+    // LCD
+    IoRegister::new("DISPCNT",     0x04000000, 2, 0xffff, 0xfff7).with_reset(0x0080),
+    IoRegister::new("GREENSWAP",   0x04000002, 2, 0x0001, 0x0001),
+    IoRegister::new("DISPSTAT",    0x04000004, 2, 0xff3f, 0xff38),
+    IoRegister::new("VCOUNT",      0x04000006, 2, 0x00ff, 0x0000),
+    IoRegister::new("BG0CNT",      0x04000008, 2, 0xdfff, 0xdfff),
+    IoRegister::new("BG1CNT",      0x0400000a, 2, 0xdfff, 0xdfff),
+    IoRegister::new("BG2CNT",      0x0400000c, 2, 0xffff, 0xffff),
+    IoRegister::new("BG3CNT",      0x0400000e, 2, 0xffff, 0xffff),
+    IoRegister::new("BG0HOFS",     0x04000010, 2, 0x0000, 0x01ff),
+    IoRegister::new("BG0VOFS",     0x04000012, 2, 0x0000, 0x01ff),
+    IoRegister::new("BG1HOFS",     0x04000014, 2, 0x0000, 0x01ff),
+    IoRegister::new("BG1VOFS",     0x04000016, 2, 0x0000, 0x01ff),
+    IoRegister::new("BG2HOFS",     0x04000018, 2, 0x0000, 0x01ff),
+    IoRegister::new("BG2VOFS",     0x0400001a, 2, 0x0000, 0x01ff),
+    IoRegister::new("BG3HOFS",     0x0400001c, 2, 0x0000, 0x01ff),
+    IoRegister::new("BG3VOFS",     0x0400001e, 2, 0x0000, 0x01ff),
+    IoRegister::new("BG2PA",       0x04000020, 2, 0x0000, 0xffff).with_reset(0x0100),
+    IoRegister::new("BG2PB",       0x04000022, 2, 0x0000, 0xffff),
+    IoRegister::new("BG2PC",       0x04000024, 2, 0x0000, 0xffff),
+    IoRegister::new("BG2PD",       0x04000026, 2, 0x0000, 0xffff).with_reset(0x0100),
+    IoRegister::new("BG2X",        0x04000028, 4, 0x00000000, 0x0fffffff),
+    IoRegister::new("BG2Y",        0x0400002c, 4, 0x00000000, 0x0fffffff),
+    IoRegister::new("BG3PA",       0x04000030, 2, 0x0000, 0xffff).with_reset(0x0100),
+    IoRegister::new("BG3PB",       0x04000032, 2, 0x0000, 0xffff),
+    IoRegister::new("BG3PC",       0x04000034, 2, 0x0000, 0xffff),
+    IoRegister::new("BG3PD",       0x04000036, 2, 0x0000, 0xffff).with_reset(0x0100),
+    IoRegister::new("BG3X",        0x04000038, 4, 0x00000000, 0x0fffffff),
+    IoRegister::new("BG3Y",        0x0400003c, 4, 0x00000000, 0x0fffffff),
+    IoRegister::new("WIN0H",       0x04000040, 2, 0x0000, 0xffff),
+    IoRegister::new("WIN1H",       0x04000042, 2, 0x0000, 0xffff),
+    IoRegister::new("WIN0V",       0x04000044, 2, 0x0000, 0xffff),
+    IoRegister::new("WIN1V",       0x04000046, 2, 0x0000, 0xffff),
+    IoRegister::new("WININ",       0x04000048, 2, 0x3f3f, 0x3f3f),
+    IoRegister::new("WINOUT",      0x0400004a, 2, 0x3f3f, 0x3f3f),
+    IoRegister::new("MOSAIC",      0x0400004c, 2, 0x0000, 0xffff),
+    IoRegister::new("BLDCNT",      0x04000050, 2, 0x3fff, 0x3fff),
+    IoRegister::new("BLDALPHA",    0x04000052, 2, 0x1f1f, 0x1f1f),
+    IoRegister::new("BLDY",        0x04000054, 2, 0x0000, 0x001f),
+
+    // Sound
+    IoRegister::new("SOUND1CNT_L", 0x04000060, 2, 0x007f, 0x007f)
+        .with_hook(IoHook::Sound),
+    IoRegister::new("SOUND1CNT_H", 0x04000062, 2, 0xffc0, 0xffff)
+        .with_hook(IoHook::Sound),
+    IoRegister::new("SOUND1CNT_X", 0x04000064, 4, 0x00004000, 0x0000c7ff)
+        .with_hook(IoHook::Sound),
+    IoRegister::new("SOUND2CNT_L", 0x04000068, 4, 0x0000ffc0, 0x0000ffff)
+        .with_hook(IoHook::Sound),
+    IoRegister::new("SOUND2CNT_H", 0x0400006c, 4, 0x00004000, 0x0000c7ff)
+        .with_hook(IoHook::Sound),
+    IoRegister::new("SOUND3CNT_L", 0x04000070, 2, 0x00e0, 0x00e0)
+        .with_hook(IoHook::Sound),
+    IoRegister::new("SOUND3CNT_H", 0x04000072, 2, 0xe000, 0xe0ff)
+        .with_hook(IoHook::Sound),
+    IoRegister::new("SOUND3CNT_X", 0x04000074, 4, 0x00004000, 0x0000c7ff)
+        .with_hook(IoHook::Sound),
+    IoRegister::new("SOUND4CNT_L", 0x04000078, 4, 0x0000ff00, 0x0000ff3f)
+        .with_hook(IoHook::Sound),
+    IoRegister::new("SOUND4CNT_H", 0x0400007c, 4, 0x000040ff, 0x0000c0ff)
+        .with_hook(IoHook::Sound),
+    IoRegister::new("SOUNDCNT_L",  0x04000080, 2, 0xff77, 0xff77)
+        .with_hook(IoHook::Sound),
+    IoRegister::new("SOUNDCNT_H",  0x04000082, 2, 0x770f, 0xff0f)
+        .with_hook(IoHook::Sound),
+    IoRegister::new("SOUNDCNT_X",  0x04000084, 4, 0x0000008f, 0x00000080)
+        .with_hook(IoHook::Sound),
+    IoRegister::new("SOUNDBIAS",   0x04000088, 4, 0x0000c3fe, 0x0000c3fe).with_reset(0x0200),
+    IoRegister::new("WAVE_RAM0",   0x04000090, 4, 0xffffffff, 0xffffffff)
+        .with_hook(IoHook::Sound),
+    IoRegister::new("WAVE_RAM1",   0x04000094, 4, 0xffffffff, 0xffffffff)
+        .with_hook(IoHook::Sound),
+    IoRegister::new("WAVE_RAM2",   0x04000098, 4, 0xffffffff, 0xffffffff)
+        .with_hook(IoHook::Sound),
+    IoRegister::new("WAVE_RAM3",   0x0400009c, 4, 0xffffffff, 0xffffffff)
+        .with_hook(IoHook::Sound),
+    IoRegister::new("FIFO_A",      0x040000a0, 4, 0x00000000, 0xffffffff)
+        .with_hook(IoHook::Sound),
+    IoRegister::new("FIFO_B",      0x040000a4, 4, 0x00000000, 0xffffffff)
+        .with_hook(IoHook::Sound),
+
+    // DMA
+    IoRegister::new("DMA0SAD",     0x040000b0, 4, 0x00000000, 0x07ffffff),
+    IoRegister::new("DMA0DAD",     0x040000b4, 4, 0x00000000, 0x07ffffff),
+    IoRegister::new("DMA0CNT_L",   0x040000b8, 2, 0x0000, 0x3fff),
+    IoRegister::new("DMA0CNT_H",   0x040000ba, 2, 0xf7e0, 0xf7e0)
+        .with_hook(IoHook::DmaControl(0)),
+    IoRegister::new("DMA1SAD",     0x040000bc, 4, 0x00000000, 0x0fffffff),
+    IoRegister::new("DMA1DAD",     0x040000c0, 4, 0x00000000, 0x07ffffff),
+    IoRegister::new("DMA1CNT_L",   0x040000c4, 2, 0x0000, 0x3fff),
+    IoRegister::new("DMA1CNT_H",   0x040000c6, 2, 0xf7e0, 0xf7e0)
+        .with_hook(IoHook::DmaControl(1)),
+    IoRegister::new("DMA2SAD",     0x040000c8, 4, 0x00000000, 0x0fffffff),
+    IoRegister::new("DMA2DAD",     0x040000cc, 4, 0x00000000, 0x07ffffff),
+    IoRegister::new("DMA2CNT_L",   0x040000d0, 2, 0x0000, 0x3fff),
+    IoRegister::new("DMA2CNT_H",   0x040000d2, 2, 0xf7e0, 0xf7e0)
+        .with_hook(IoHook::DmaControl(2)),
+    IoRegister::new("DMA3SAD",     0x040000d4, 4, 0x00000000, 0x0fffffff),
+    IoRegister::new("DMA3DAD",     0x040000d8, 4, 0x00000000, 0x0fffffff),
+    IoRegister::new("DMA3CNT_L",   0x040000dc, 2, 0x0000, 0xffff),
+    IoRegister::new("DMA3CNT_H",   0x040000de, 2, 0xffe0, 0xffe0)
+        .with_hook(IoHook::DmaControl(3)),
+
+    // Timers
+    IoRegister::new("TM0CNT_L",    0x04000100, 2, 0xffff, 0xffff)
+        .with_hook(IoHook::TimerReload(0)),
+    IoRegister::new("TM0CNT_H",    0x04000102, 2, 0x00c3, 0x00c3)
+        .with_hook(IoHook::TimerControl(0)),
+    IoRegister::new("TM1CNT_L",    0x04000104, 2, 0xffff, 0xffff)
+        .with_hook(IoHook::TimerReload(1)),
+    IoRegister::new("TM1CNT_H",    0x04000106, 2, 0x00c7, 0x00c7)
+        .with_hook(IoHook::TimerControl(1)),
+    IoRegister::new("TM2CNT_L",    0x04000108, 2, 0xffff, 0xffff)
+        .with_hook(IoHook::TimerReload(2)),
+    IoRegister::new("TM2CNT_H",    0x0400010a, 2, 0x00c7, 0x00c7)
+        .with_hook(IoHook::TimerControl(2)),
+    IoRegister::new("TM3CNT_L",    0x0400010c, 2, 0xffff, 0xffff)
+        .with_hook(IoHook::TimerReload(3)),
+    IoRegister::new("TM3CNT_H",    0x0400010e, 2, 0x00c7, 0x00c7)
+        .with_hook(IoHook::TimerControl(3)),
+
+    // Serial communication (1)
+    IoRegister::new("SIODATA32",   0x04000120, 4, 0xffffffff, 0xffffffff),
+    IoRegister::new("SIOMULTI2",   0x04000124, 2, 0xffff, 0xffff),
+    IoRegister::new("SIOMULTI3",   0x04000126, 2, 0xffff, 0xffff),
+    IoRegister::new("SIOCNT",      0x04000128, 2, 0x7fff, 0x7fff),
+    IoRegister::new("SIODATA8",    0x0400012a, 2, 0xffff, 0xffff),
+
+    // Keypad
+    IoRegister::new("KEYINPUT",    0x04000130, 2, 0x03ff, 0x0000).with_reset(0x03ff),
+    IoRegister::new("KEYCNT",      0x04000132, 2, 0xc3ff, 0xc3ff),
+
+    // Serial communication (2)
+    IoRegister::new("RCNT",        0x04000134, 2, 0xc1ff, 0xc1ff).with_reset(0x8000),
+    IoRegister::new("JOYCNT",      0x04000140, 2, 0x0047, 0x0047),
+    IoRegister::new("JOY_RECV",    0x04000150, 4, 0xffffffff, 0xffffffff),
+    IoRegister::new("JOY_TRANS",   0x04000154, 4, 0xffffffff, 0xffffffff),
+    IoRegister::new("JOYSTAT",     0x04000158, 2, 0x003a, 0x0030),
+
+    // Interrupts, waitstates and power down control
+    IoRegister::new("IE",          0x04000200, 2, 0x3fff, 0x3fff),
+    IoRegister::new("IF",          0x04000202, 2, 0x3fff, 0x0000)
+        .with_hook(IoHook::InterruptAcknowledge),
+    IoRegister::new("WAITCNT",     0x04000204, 4, 0x00005fff, 0x00005fff)
+        .with_hook(IoHook::WaitControl),
+    IoRegister::new("IME",         0x04000208, 4, 0x00000001, 0x00000001),
+    IoRegister::new("POSTFLG",     0x04000300, 2, 0x0001, 0x8001).with_reset(0x0001),
+];
+
+/// registers::IoRegisters
+///
+/// Storage of the I/O registers, accessed by the cpu through the masks of `IO_REGISTERS`. The
+/// devices owning the registers use `get` and `set`, which bypass the masks.
+pub struct IoRegisters {
+    registers: Memory,
+    lookup: Vec<Option<&'static IoRegister>>, // Register containing each halfword of the region
+}
+
+impl IoRegisters {
+    pub fn new() -> Self {
+        let mut lookup: Vec<Option<&'static IoRegister>> = vec![None; (IO_SIZE >> 1) as usize];
+
+        for register in IO_REGISTERS {
+            for address in (register.address..register.address + register.size).step_by(2) {
+                let index = ((address - IO_INIT_ADDR) >> 1) as usize;
+                if let Some(other) = lookup[index] {
+                    panic!("I/O register {} overlaps {}", register.name, other.name);
+                }
+                lookup[index] = Some(register);
+            }
+        }
+
+        let mut io = Self {
+            registers: Memory::new(IO_INIT_ADDR, IO_SIZE, false, String::from("I/O REGISTERS")),
+            lookup,
+        };
+        io.reset();
+        io
+    }
+
+    /// IoRegisters::reset
+    ///
+    /// Set all the registers to their reset value.
+    pub fn reset(&mut self) {
+        self.registers.reset(None);
+        for register in IO_REGISTERS {
+            self.set(register.address, register.reset_value & 0xffff);
+            if register.size == 4 {
+                self.set(register.address + 2, register.reset_value >> 16);
+            }
+        }
+    }
+
+    /// IoRegisters::register
+    ///
+    /// @param address [u32]: address inside the I/O region
+    /// @return [Option<&IoRegister>]: register containing the address, if any
+    pub fn register(&self, address: u32) -> Option<&'static IoRegister> {
+        self.lookup[((address - IO_INIT_ADDR) >> 1) as usize]
+    }
+
+    /// IoRegisters::get
+    ///
+    /// @param address [u32]: address of the halfword
+    /// @return [u32]: raw content of the halfword
+    pub fn get(&self, address: u32) -> u32 {
+        self.registers.read_halfword(address)
+    }
+
+    /// IoRegisters::get_word
+    ///
+    /// @param address [u32]: address of the word
+    /// @return [u32]: raw content of the word
+    pub fn get_word(&self, address: u32) -> u32 {
+        self.registers.read_word(address)
+    }
+
+    /// IoRegisters::set
+    ///
+    /// Modify a halfword from the hardware side, ignoring the write mask of the register.
+    ///
+    /// @param address [u32]: address of the halfword
+    /// @param value [u32]: new content of the halfword
+    pub fn set(&mut self, address: u32, value: u32) {
+        self.registers.write16(address, value as u16);
+    }
+
+    /// IoRegisters::read
+    ///
+    /// Read the aligned word containing `address`. Each halfword which does not belong to a
+    /// readable register takes its value from the open bus.
+    ///
+    /// @param address [u32]: address of the access
+    /// @param open_bus [u32]: value on the bus in case of unreadable registers
+    /// @return [u32]: data read
+    pub fn read(&self, address: u32, open_bus: u32) -> u32 {
+        let address = address & !3;
+
+        (0..2).fold(0, |data, half| {
+            let halfword_address = address + half * 2;
+            let value = match self.register(halfword_address) {
+                Some(register) if register.read_mask != 0 => {
+                    let shift = (halfword_address - register.address) * 8;
+                    self.get(halfword_address) & (register.read_mask >> shift) & 0xffff
+                }
+                _ => (open_bus >> (half * 16)) & 0xffff,
+            };
+            data | (value << (half * 16))
+        })
+    }
+
+    /// IoRegisters::write
+    ///
+    /// Write the data on the bus to the registers, through their write masks.
+    ///
+    /// @param address [u32]: address of the access
+    /// @param data [u32]: data on the bus
+    /// @param mas [TransferSize]: size of the access
+    /// @return [[Option<IoEvent>; 2]]: hooks to be handled, one for each halfword written
+    pub fn write(&mut self, address: u32, data: u32, mas: TransferSize) -> [Option<IoEvent>; 2] {
+        match mas {
+            TransferSize::WORD => [
+                self.write_halfword(address & !3, data & 0xffff, 0xffff),
+                self.write_halfword((address & !3) + 2, data >> 16, 0xffff),
+            ],
+            TransferSize::HALFWORD => {
+                let value = (data >> ((address & 2) * 8)) & 0xffff;
+                [self.write_halfword(address & !1, value, 0xffff), None]
+            }
+            TransferSize::BYTE => {
+                let byte = (data >> ((address & 3) * 8)) & 0xff;
+                let lanes = 0xff << ((address & 1) * 8);
+                [
+                    self.write_halfword(address & !1, byte | (byte << 8), lanes),
+                    None,
+                ]
+            }
+        }
+    }
+
+    /// IoRegisters::write_halfword
+    ///
+    /// @param address [u32]: address of the halfword
+    /// @param value [u32]: value to write
+    /// @param lanes [u32]: bits of the halfword involved in the transfer
+    /// @return [Option<IoEvent>]: hook to be handled, if any
+    fn write_halfword(&mut self, address: u32, value: u32, lanes: u32) -> Option<IoEvent> {
+        let register = self.register(address)?;
+        let shift = (address - register.address) * 8;
+        let mask = (register.write_mask >> shift) & lanes;
+
+        let old_value = self.get(address);
+        self.set(address, (old_value & !mask) | (value & mask));
+
+        register.hook.map(|hook| IoEvent {
+            hook,
+            address,
+            data: value & lanes,
+            lanes,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test_registers {
+
+    use crate::bus::TransferSize;
+    use crate::io::registers::{
+        IoEvent, IoHook, IoRegisters, DISPCNT, DISPSTAT, IF, KEYINPUT, VCOUNT, WAITCNT,
+    };
+
+    #[test]
+    fn test_masks() {
+        let mut io = IoRegisters::new();
+
+        // Read-only bits are not modified by the cpu, but they are by the hardware
+        io.set(VCOUNT, 0x9f);
+        io.set(DISPSTAT, 0x0001);
+        io.write(DISPSTAT, 0x1234ffff, TransferSize::WORD);
+        assert_eq!(io.get(DISPSTAT), 0xff39);
+        assert_eq!(io.read(DISPSTAT, 0), 0x009fff39);
+
+        // Write-only registers and unused addresses return the open bus value
+        io.write(0x04000010, 0x00ab00cd, TransferSize::WORD);
+        assert_eq!(io.get(0x04000010), 0xcd);
+        assert_eq!(io.read(0x04000010, 0xdeadbeef), 0xdeadbeef);
+        assert_eq!(io.read(0x0400004e, 0xdeadbeef), 0xdeadbeef);
+        assert_eq!(io.read(0x04000054, 0xdeadbeef), 0xdeadbeef);
+
+        // Unused bits of readable registers are read as zero
+        io.write(0x04000064, 0xffffffff, TransferSize::WORD);
+        assert_eq!(io.read(0x04000064, 0xdeadbeef), 0x00004000);
+
+        // Byte writes only modify their lane
+        io.write(0x04000009, 0x00001200, TransferSize::BYTE);
+        io.write(0x04000008, 0x00000034, TransferSize::BYTE);
+        assert_eq!(io.read(0x04000008, 0), 0x1234);
+    }
+
+    #[test]
+    fn test_hooks() {
+        let mut io = IoRegisters::new();
+
+        let events = io.write(IF, 0x00050000, TransferSize::WORD);
+        assert_eq!(events[0], None);
+        assert_eq!(
+            events[1],
+            Some(IoEvent {
+                hook: IoHook::InterruptAcknowledge,
+                address: IF,
+                data: 0x0005,
+                lanes: 0xffff
+            })
+        );
+
+        let events = io.write(WAITCNT, 0xc7d7, TransferSize::HALFWORD);
+        assert_eq!(events[0].unwrap().hook, IoHook::WaitControl);
+        assert_eq!(io.read(WAITCNT, 0), 0x47d7);
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut io = IoRegisters::new();
+        assert_eq!(io.read(KEYINPUT, 0), 0x03ff);
+        assert_eq!(io.read(0x04000088, 0), 0x0200);
+        assert_eq!(io.get(DISPCNT), 0x0080);
+
+        io.write(DISPCNT, 0x0403, TransferSize::HALFWORD);
+        io.set(KEYINPUT, 0x0001);
+        io.reset();
+        assert_eq!(io.get(DISPCNT), 0x0080);
+        assert_eq!(io.get(KEYINPUT), 0x03ff);
+    }
+}
@@ -0,0 +1,199 @@
+use sdl2::keyboard::Keycode;
+use std::str::FromStr;
+
+/// Position of an analog stick below which it is considered at rest
+const STICK_DEADZONE: i16 = 8000;
+
+/// sensor_input::SensorAction
+///
+/// Actions on the sensors of the game pak which can be bound to a key.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SensorAction {
+    LightUp,
+    LightDown,
+    TiltLeft,
+    TiltRight,
+    TiltUp,
+    TiltDown,
+    RotateLeft,
+    RotateRight,
+}
+
+impl FromStr for SensorAction {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "light-up" => Ok(SensorAction::LightUp),
+            "light-down" => Ok(SensorAction::LightDown),
+            "tilt-left" => Ok(SensorAction::TiltLeft),
+            "tilt-right" => Ok(SensorAction::TiltRight),
+            "tilt-up" => Ok(SensorAction::TiltUp),
+            "tilt-down" => Ok(SensorAction::TiltDown),
+            "rotate-left" => Ok(SensorAction::RotateLeft),
+            "rotate-right" => Ok(SensorAction::RotateRight),
+            _ => Err(format!("unknown sensor action {}", name)),
+        }
+    }
+}
+
+/// sensor_input::Stick
+///
+/// Analog stick of the game controller driving the tilt and rotation sensors.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Stick {
+    Left,
+    Right,
+}
+
+impl FromStr for Stick {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "left" => Ok(Stick::Left),
+            "right" => Ok(Stick::Right),
+            _ => Err(format!("unknown stick {}, expected left or right", name)),
+        }
+    }
+}
+
+/// sensor_input::SensorChanges
+///
+/// Changes of the sensors requested by the user since the last update. Tilt and rotation are
+/// given from -32767 to 32767 on each axis, and they are only reported while the controls are
+/// used (and once more when they are released), so that values set through the API are kept
+/// otherwise.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct SensorChanges {
+    pub light_delta: i32,
+    pub tilt: Option<(i16, i16)>,
+    pub rotation: Option<i16>,
+}
+
+/// sensor_input::SensorInput
+///
+/// Maps keys and an analog stick to the sensors of the game pak. Default keys:
+///
+/// Action              Key
+/// -------------------------------------------------------
+/// Light up / down     Page Up / Page Down
+/// Tilt                Arrows
+/// Rotate              Comma / Period
+/// -------------------------------------------------------
+pub struct SensorInput {
+    bindings: Vec<(SensorAction, Keycode)>,
+    pub stick: Option<Stick>,
+    tilt_active: bool,
+    rotation_active: bool,
+}
+
+impl SensorInput {
+    pub fn new() -> Self {
+        Self {
+            bindings: vec![
+                (SensorAction::LightUp, Keycode::PageUp),
+                (SensorAction::LightDown, Keycode::PageDown),
+                (SensorAction::TiltLeft, Keycode::Left),
+                (SensorAction::TiltRight, Keycode::Right),
+                (SensorAction::TiltUp, Keycode::Up),
+                (SensorAction::TiltDown, Keycode::Down),
+                (SensorAction::RotateLeft, Keycode::Comma),
+                (SensorAction::RotateRight, Keycode::Period),
+            ],
+            stick: None,
+            tilt_active: false,
+            rotation_active: false,
+        }
+    }
+
+    /// SensorInput::set_binding
+    ///
+    /// @param action [SensorAction]: action to bind
+    /// @param key [Keycode]: key performing the action
+    pub fn set_binding(&mut self, action: SensorAction, key: Keycode) {
+        self.bindings.retain(|(bound, _)| *bound != action);
+        self.bindings.push((action, key));
+    }
+
+    /// SensorInput::update
+    ///
+    /// @param is_key_held [F]: function telling whether a key is held
+    /// @param stick [Option<(i16, i16)>]: position of the configured stick, if any
+    /// @return [SensorChanges]: changes to apply to the sensors
+    pub fn update<F: Fn(Keycode) -> bool>(
+        &mut self,
+        is_key_held: F,
+        stick: Option<(i16, i16)>,
+    ) -> SensorChanges {
+        let axis = |negative: SensorAction, positive: SensorAction| {
+            let held = |action: SensorAction| {
+                self.bindings
+                    .iter()
+                    .any(|(bound, key)| *bound == action && is_key_held(*key))
+            };
+            (held(positive) as i32 - held(negative) as i32) * i16::MAX as i32
+        };
+
+        let light_delta = axis(SensorAction::LightDown, SensorAction::LightUp) / i16::MAX as i32;
+        let mut tilt = (
+            axis(SensorAction::TiltLeft, SensorAction::TiltRight) as i16,
+            axis(SensorAction::TiltUp, SensorAction::TiltDown) as i16,
+        );
+        let mut rotation = axis(SensorAction::RotateLeft, SensorAction::RotateRight) as i16;
+
+        if let Some((x, y)) = stick {
+            if x.unsigned_abs().max(y.unsigned_abs()) > STICK_DEADZONE as u16 {
+                tilt = (x.max(-i16::MAX), y.max(-i16::MAX));
+                rotation = x.max(-i16::MAX);
+            }
+        }
+
+        let tilt_active = tilt != (0, 0);
+        let rotation_active = rotation != 0;
+        let changes = SensorChanges {
+            light_delta,
+            tilt: (tilt_active || self.tilt_active).then_some(tilt),
+            rotation: (rotation_active || self.rotation_active).then_some(rotation),
+        };
+        self.tilt_active = tilt_active;
+        self.rotation_active = rotation_active;
+        changes
+    }
+}
+
+#[cfg(test)]
+mod test_sensor_input {
+
+    use crate::io::sensor_input::{SensorAction, SensorChanges, SensorInput};
+    use sdl2::keyboard::Keycode;
+
+    #[test]
+    fn test_update() {
+        let mut input = SensorInput::new();
+        assert_eq!(input.update(|_| false, None), SensorChanges::default());
+
+        let changes = input.update(|key| key == Keycode::Left || key == Keycode::PageUp, None);
+        assert_eq!(changes.light_delta, 1);
+        assert_eq!(changes.tilt, Some((-i16::MAX, 0)));
+        assert_eq!(changes.rotation, None);
+
+        // The release is reported once
+        assert_eq!(input.update(|_| false, None).tilt, Some((0, 0)));
+        assert_eq!(input.update(|_| false, None).tilt, None);
+
+        // Rebound keys and analog stick
+        input.set_binding(SensorAction::RotateRight, Keycode::R);
+        assert_eq!(
+            input.update(|key| key == Keycode::R, None).rotation,
+            Some(i16::MAX)
+        );
+        assert_eq!(input.update(|_| false, Some((100, -100))).rotation, Some(0));
+        let changes = input.update(|_| false, Some((-32768, 20000)));
+        assert_eq!(changes.tilt, Some((-i16::MAX, 20000)));
+        assert_eq!(changes.rotation, Some(-i16::MAX));
+
+        assert_eq!("tilt-up".parse(), Ok(SensorAction::TiltUp));
+        assert!("jump".parse::<SensorAction>().is_err());
+    }
+}
@@ -0,0 +1,141 @@
+use crate::common::BitOperation;
+use crate::io::registers::{IoRegisters, IF};
+
+/// Address of TMxCNT_L for each timer, TMxCNT_H being at +2
+pub const TIMER_BASE: [u32; 4] = [0x04000100, 0x04000104, 0x04000108, 0x0400010c];
+
+/// Cycles per tick for each prescaler selection of TMxCNT_H
+const PRESCALERS: [u32; 4] = [1, 64, 256, 1024];
+
+/// timers::Timers
+///
+/// The 4 incrementing 16 bits timers. TMxCNT_L holds the current counter when read, while writes
+/// set the reload value, which is loaded when the timer starts and after each overflow. TMxCNT_H
+/// selects the prescaler (bits 0-1), the count-up mode where the timer ticks on overflows of the
+/// previous one (bit 2, timers 1-3), the interrupt request (bit 6) and starts the timer (bit 7).
+pub struct Timers {
+    counters: [u32; 4],
+    reloads: [u32; 4],
+    prescalers: [u32; 4],
+    running: [bool; 4],
+}
+
+impl Timers {
+    pub fn new() -> Self {
+        Self {
+            counters: [0; 4],
+            reloads: [0; 4],
+            prescalers: [0; 4],
+            running: [false; 4],
+        }
+    }
+
+    /// Timers::reload_written
+    ///
+    /// Handle a write to TMxCNT_L: the value becomes the reload value, and the register keeps
+    /// showing the counter.
+    ///
+    /// @param timer [usize]: index of the timer
+    /// @param data [u32]: data written to the register
+    /// @param lanes [u32]: bits of the register involved in the transfer
+    /// @param io [&mut IoRegisters]: I/O registers
+    pub fn reload_written(&mut self, timer: usize, data: u32, lanes: u32, io: &mut IoRegisters) {
+        self.reloads[timer] = (self.reloads[timer] & !lanes) | (data & lanes);
+        io.set(TIMER_BASE[timer], self.counters[timer]);
+    }
+
+    /// Timers::control_written
+    ///
+    /// Handle a write to TMxCNT_H: starting a timer loads its counter with the reload value.
+    ///
+    /// @param timer [usize]: index of the timer
+    /// @param io [&mut IoRegisters]: I/O registers
+    pub fn control_written(&mut self, timer: usize, io: &mut IoRegisters) {
+        let running = io.get(TIMER_BASE[timer] + 2).is_bit_set(7);
+        if running && !self.running[timer] {
+            self.counters[timer] = self.reloads[timer];
+            self.prescalers[timer] = 0;
+            io.set(TIMER_BASE[timer], self.counters[timer]);
+        }
+        self.running[timer] = running;
+    }
+
+    /// Timers::step
+    ///
+    /// Advance the timers by one cycle, and raise the interrupt requests of those overflowing.
+    ///
+    /// @param io [&mut IoRegisters]: I/O registers
+    /// @return [u32]: bit i set if timer i overflowed
+    pub fn step(&mut self, io: &mut IoRegisters) -> u32 {
+        let mut overflows = 0;
+
+        for (timer, &base) in TIMER_BASE.iter().enumerate() {
+            if !self.running[timer] {
+                continue;
+            }
+
+            let control = io.get(base + 2);
+            let tick = if timer > 0 && control.is_bit_set(2) {
+                overflows.is_bit_set(timer as u32 - 1)
+            } else {
+                self.prescalers[timer] += 1;
+                if self.prescalers[timer] >= PRESCALERS[control.get_range(1, 0) as usize] {
+                    self.prescalers[timer] = 0;
+                    true
+                } else {
+                    false
+                }
+            };
+            if !tick {
+                continue;
+            }
+
+            self.counters[timer] += 1;
+            if self.counters[timer] > 0xffff {
+                self.counters[timer] = self.reloads[timer];
+                overflows = overflows.set_bit(timer as u32);
+                if control.is_bit_set(6) {
+                    io.set(IF, io.get(IF).set_bit(3 + timer as u32));
+                }
+            }
+            io.set(base, self.counters[timer]);
+        }
+
+        overflows
+    }
+}
+
+#[cfg(test)]
+mod test_timers {
+
+    use crate::bus::TransferSize;
+    use crate::io::registers::{IoRegisters, IF};
+    use crate::io::timers::{Timers, TIMER_BASE};
+
+    #[test]
+    fn test_prescaler_and_cascade() {
+        let mut io = IoRegisters::new();
+        let mut timers = Timers::new();
+
+        // Timer 0 overflows every 2 x 64 cycles, timer 1 counts its overflows
+        io.write(TIMER_BASE[0], 0x0081_fffe, TransferSize::WORD);
+        timers.reload_written(0, 0xfffe, 0xffff, &mut io);
+        timers.control_written(0, &mut io);
+        io.write(TIMER_BASE[1], 0x00c4_ffff, TransferSize::WORD);
+        timers.reload_written(1, 0xffff, 0xffff, &mut io);
+        timers.control_written(1, &mut io);
+        assert_eq!(io.get(TIMER_BASE[0]), 0xfffe);
+
+        let overflows: Vec<u32> = (0..128).map(|_| timers.step(&mut io)).collect();
+        assert_eq!(io.get(TIMER_BASE[0]), 0xfffe);
+        assert_eq!(overflows[63], 0);
+        assert_eq!(overflows[127], 0b11);
+        assert_eq!(overflows.iter().filter(|o| **o != 0).count(), 1);
+        assert_eq!(io.get(IF), 0x0010);
+
+        // Writing the reload value doesn't change the counter
+        io.write(TIMER_BASE[0], 0x0081_1234, TransferSize::WORD);
+        timers.reload_written(0, 0x1234, 0xffff, &mut io);
+        assert_eq!(io.get(TIMER_BASE[0]), 0xfffe);
+    }
+}
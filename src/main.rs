@@ -2,22 +2,221 @@ extern crate num;
 #[macro_use]
 extern crate num_derive;
 extern crate sdl2;
+use apu::audio_output::AudioOutput;
+use backup::save_file::SaveFile;
+use backup::BackupType;
+use cartridge::game_database::{GameDatabase, GameSettings};
+use cartridge::rtc::RtcClock;
+use io::keypad::Button;
+use io::sensor_input::{SensorAction, Stick};
+use sdl2::keyboard::Keycode;
 use std::env;
+use std::path::Path;
+
+/// Cycles of a frame: 228 lines of 1232 cycles
+const CYCLES_PER_FRAME: u64 = 280896;
+
+mod apu;
 mod arm7_tdmi;
+mod backup;
 mod bus;
+mod cartridge;
 mod common;
 mod gpu;
 mod io;
 mod memory;
 
 fn main() {
+    let mut positional_args = Vec::new();
+    let mut ram_seed = None;
+    let mut save_dir = None;
+    let mut save_type = None;
+    let mut info = false;
+    let mut overrides_file = None;
+    let mut rtc_clock = RtcClock::Host { offset: 0 };
+    let mut sensor_keys = Vec::new();
+    let mut sensor_stick = None;
+    let mut turbo_rates = Vec::new();
+    let mut volume = 100;
+    let mut audio = true;
+    let mut record_audio = None;
+    let mut record_channels = false;
+    let mut frames = None;
+    let mut headless = false;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            // Fill the RAMs with random content, to spot reads of uninitialized memory
+            "--ram-seed" => {
+                let seed = args.next().expect("--ram-seed requires a value");
+                ram_seed = Some(seed.parse::<u64>().expect("--ram-seed must be a number"));
+            }
+            // Directory of the save files, instead of the one of the rom
+            "--save-dir" => save_dir = Some(args.next().expect("--save-dir requires a value")),
+            // Backup memory of the game, when the detection from the rom picks the wrong one
+            "--save-type" => {
+                let name = args.next().expect("--save-type requires a value");
+                save_type = Some(
+                    name.parse::<BackupType>()
+                        .unwrap_or_else(|e| panic!("{}", e)),
+                );
+            }
+            // File of per-game settings, extending the built-in database
+            "--overrides" => {
+                overrides_file = Some(args.next().expect("--overrides requires a value"));
+            }
+            // Fixed time of the real time clock, as YYYY-MM-DDTHH:MM:SS
+            "--rtc-time" => {
+                let date = args.next().expect("--rtc-time requires a value");
+                let time = RtcClock::parse_datetime(&date).unwrap_or_else(|e| panic!("{}", e));
+                rtc_clock = RtcClock::Fixed(time);
+            }
+            // Seconds added to the host time by the real time clock
+            "--rtc-offset" => {
+                let offset = args.next().expect("--rtc-offset requires a value");
+                let offset = offset
+                    .parse::<i64>()
+                    .expect("--rtc-offset must be a number");
+                rtc_clock = RtcClock::Host { offset };
+            }
+            // Key bound to an action on the sensors of the game pak, as <action>=<key name>
+            "--sensor-key" => {
+                let binding = args.next().expect("--sensor-key requires a value");
+                let (action, key) = binding
+                    .split_once('=')
+                    .expect("--sensor-key expects <action>=<key>");
+                let action = action
+                    .parse::<SensorAction>()
+                    .unwrap_or_else(|e| panic!("{}", e));
+                let key = Keycode::from_name(key).unwrap_or_else(|| panic!("unknown key {}", key));
+                sensor_keys.push((action, key));
+            }
+            // Analog stick of the game controller driving the tilt and gyro sensors
+            "--sensor-stick" => {
+                let stick = args.next().expect("--sensor-stick requires a value");
+                sensor_stick = Some(stick.parse::<Stick>().unwrap_or_else(|e| panic!("{}", e)));
+            }
+            // Frames between two toggles of the turbo variant of a button (a, b, l or r)
+            "--turbo-rate" => {
+                let setting = args.next().expect("--turbo-rate requires a value");
+                let (button, rate) = setting
+                    .split_once('=')
+                    .expect("--turbo-rate expects <button>=<frames>");
+                let button = button.parse::<Button>().unwrap_or_else(|e| panic!("{}", e));
+                let rate = rate
+                    .parse::<u32>()
+                    .expect("--turbo-rate frames must be a number");
+                turbo_rates.push((button, rate));
+            }
+            // Master volume of the audio output, from 0 to 100
+            "--volume" => {
+                let value = args.next().expect("--volume requires a value");
+                volume = value
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|volume| *volume <= 100)
+                    .expect("--volume must be a number from 0 to 100");
+            }
+            // Don't open the audio device
+            "--no-audio" => audio = false,
+            // Record the sound to a WAV file
+            "--record-audio" => {
+                record_audio = Some(args.next().expect("--record-audio requires a value"));
+            }
+            // Also record each sound channel to its own WAV file, next to the one of --record-audio
+            "--record-channels" => record_channels = true,
+            // Stop after the given number of frames
+            "--frames" => {
+                let count = args.next().expect("--frames requires a value");
+                frames = Some(count.parse::<u64>().expect("--frames must be a number"));
+            }
+            // Don't open the window nor poll the keyboard, e.g. to record the audio with --frames
+            "--headless" => headless = true,
+            // Print the header of the rom and exit
+            "--info" => info = true,
+            _ => positional_args.push(arg),
+        }
+    }
+
     let mut gba = bus::Bus::new();
-    let rom_file = env::args().nth(1).expect("gba rom must be provided");
-    let bios_file = env::args().nth(2).expect("bios file must be provided");
-    gba.gamepak.init_from_file(&String::from(&rom_file));
-    gba.bios.init_from_file(&String::from(&bios_file));
+    let rom_file = positional_args.first().expect("gba rom must be provided");
+    gba.gamepak.init_from_file(rom_file);
+    let header = gba.cartridge_header();
+
+    if info {
+        match &header {
+            Some(header) => println!("{}", header),
+            None => println!("{} is too small to contain a header", rom_file),
+        }
+        return;
+    }
+
+    match &header {
+        Some(header) if header.is_valid() => println!("{} ({})", header.title, header.game_code),
+        _ => println!("Warning: the header of the rom would be rejected by the bios"),
+    }
+
+    let bios_file = positional_args.get(1).expect("bios file must be provided");
+    gba.bios.init_from_file(bios_file);
+    gba.reset(ram_seed);
 
-    loop {
+    let mut database = GameDatabase::new();
+    if let Some(file) = &overrides_file {
+        database
+            .load_overrides(Path::new(file))
+            .unwrap_or_else(|e| panic!("{}", e));
+    }
+    let mut settings = header
+        .as_ref()
+        .map(|header| database.lookup(&header.game_code))
+        .unwrap_or(GameSettings::NONE);
+    let backup_type = save_type
+        .or(settings.save_type)
+        .or_else(|| BackupType::detect(gba.gamepak.as_bytes()))
+        .unwrap_or(BackupType::Sram);
+    println!("Save type: {:?}", backup_type);
+    settings.save_type = Some(backup_type);
+    gba.configure(settings, rtc_clock);
+    gba.set_headless(headless);
+    for (action, key) in sensor_keys {
+        gba.keypad.sensor_input.set_binding(action, key);
+    }
+    gba.keypad.sensor_input.stick = sensor_stick;
+    for (button, rate) in turbo_rates {
+        gba.keypad.automation.set_turbo_rate(button, rate);
+    }
+
+    if audio {
+        match AudioOutput::new(volume) {
+            Ok(audio) => gba.audio = Some(audio),
+            Err(e) => println!("Warning: no audio output ({})", e),
+        }
+    }
+    if let Some(file) = &record_audio {
+        gba.start_audio_capture(Path::new(file), record_channels)
+            .unwrap_or_else(|e| panic!("Unable to create {}: {}", file, e));
+    }
+
+    let game_code = header
+        .as_ref()
+        .filter(|header| header.is_valid())
+        .map(|header| header.game_code.as_str());
+    gba.load_save(SaveFile::path_for_rom(
+        rom_file,
+        save_dir.as_deref(),
+        game_code,
+    ));
+
+    let max_steps = frames.map(|frames| frames * CYCLES_PER_FRAME);
+    let mut steps = 0;
+    while gba.is_running() && max_steps.is_none_or(|max_steps| steps < max_steps) {
         gba.step();
+        steps += 1;
+    }
+
+    gba.flush_save();
+    if let Err(e) = gba.stop_audio_capture() {
+        println!("Unable to complete the audio recording: {}", e);
     }
 }
@@ -1,12 +1,15 @@
-use crate::{bus::TransferSize, common::BitOperation};
-use byteorder::{LittleEndian, ReadBytesExt};
+use crate::bus::TransferSize;
 use std::fs::File;
-use std::io::Cursor;
 use std::io::Read;
 
+/// memory::Memory
+///
+/// Little-endian byte buffer mapped at `init_address`. Every access validates its range once, and
+/// then reads or writes the buffer without further bounds checks. 16 and 32 bits accesses are
+/// aligned to their size, as the hardware does.
 pub struct Memory {
     is_read_only: bool,
-    pub data: Vec<u32>,
+    data: Vec<u8>,
     init_address: u32,
     size: u32,
     name: String,
@@ -14,7 +17,7 @@ pub struct Memory {
 
 impl Memory {
     pub fn new(init_address: u32, size: u32, rom: bool, name: String) -> Self {
-        let data = vec![0 as u32; (size >> 2) as usize];
+        let data = vec![0_u8; size as usize];
 
         Self {
             is_read_only: rom,
@@ -25,64 +28,158 @@ impl Memory {
         }
     }
 
-    pub fn read(&self, address: u32, _mas: TransferSize) -> u32 {
-        if address - self.init_address > self.size {
+    /// Memory::offset
+    ///
+    /// Validate an access and convert its address into an offset in the buffer. The subtraction
+    /// wraps, so that addresses below `init_address` are rejected as well.
+    ///
+    /// @param address [u32]: address of the access
+    /// @param len [u32]: number of bytes of the access
+    /// @return [usize]: offset of the first byte in the buffer
+    #[inline(always)]
+    fn offset(&self, address: u32, len: u32) -> usize {
+        let offset = address.wrapping_sub(self.init_address);
+        if offset >= self.size || self.size - offset < len {
             panic!(
-                "Address is to valid while accessing {}: {:#08x}",
+                "Invalid address while accessing {}: {:#010x}",
                 self.name, address
             );
         }
+        offset as usize
+    }
 
-        // TODO: What happens for misaligned addresses?
+    /// Memory::read8
+    ///
+    /// @param address [u32]: address to read
+    /// @return [u8]: byte at `address`
+    #[inline(always)]
+    pub fn read8(&self, address: u32) -> u8 {
+        let offset = self.offset(address, 1);
+        // SAFETY: `offset` validated the byte to be inside the buffer
+        unsafe { *self.data.get_unchecked(offset) }
+    }
 
-        self.data[((address - self.init_address) >> 2) as usize]
+    /// Memory::read16
+    ///
+    /// @param address [u32]: address to read, aligned to 2
+    /// @return [u16]: halfword at `address`
+    #[inline(always)]
+    pub fn read16(&self, address: u32) -> u16 {
+        let offset = self.offset(address & !1, 2);
+        // SAFETY: `offset` validated the two bytes to be inside the buffer
+        unsafe { u16::from_le_bytes(*(self.data.as_ptr().add(offset) as *const [u8; 2])) }
     }
 
-    pub fn read_byte(&self, address: u32) -> u32 {
-        if address - self.init_address > self.size {
-            panic!(
-                "Address is to valid while accessing {}: {:#08x}",
-                self.name, address
-            );
-        }
+    /// Memory::read32
+    ///
+    /// @param address [u32]: address to read, aligned to 4
+    /// @return [u32]: word at `address`
+    #[inline(always)]
+    pub fn read32(&self, address: u32) -> u32 {
+        let offset = self.offset(address & !3, 4);
+        // SAFETY: `offset` validated the four bytes to be inside the buffer
+        unsafe { u32::from_le_bytes(*(self.data.as_ptr().add(offset) as *const [u8; 4])) }
+    }
 
-        let offset = address % 4;
-        let data_to_return = self.data[((address - self.init_address) >> 2) as usize];
-        data_to_return.get_range(offset * 8 + 7, offset * 8)
+    /// Memory::write8
+    ///
+    /// Write a byte, regardless of the memory being read-only.
+    ///
+    /// @param address [u32]: address to write
+    /// @param data [u8]: byte to write
+    #[inline(always)]
+    pub fn write8(&mut self, address: u32, data: u8) {
+        let offset = self.offset(address, 1);
+        // SAFETY: `offset` validated the byte to be inside the buffer
+        unsafe { *self.data.get_unchecked_mut(offset) = data }
     }
 
-    pub fn read_halfword(&self, address: u32) -> u32 {
-        if address - self.init_address > self.size {
-            panic!(
-                "Address is to valid while accessing {}: {:#08x}",
-                self.name, address
-            );
-        }
+    /// Memory::write16
+    ///
+    /// Write a halfword, regardless of the memory being read-only.
+    ///
+    /// @param address [u32]: address to write, aligned to 2
+    /// @param data [u16]: halfword to write
+    #[inline(always)]
+    pub fn write16(&mut self, address: u32, data: u16) {
+        let offset = self.offset(address & !1, 2);
+        // SAFETY: `offset` validated the two bytes to be inside the buffer
+        unsafe { *(self.data.as_mut_ptr().add(offset) as *mut [u8; 2]) = data.to_le_bytes() }
+    }
 
-        let offset = address.is_bit_set(1) as u32;
-        let data_to_return = self.data[((address - self.init_address) >> 2) as usize];
-        data_to_return.get_range(offset * 16 + 15, offset * 16)
+    /// Memory::write32
+    ///
+    /// Write a word, regardless of the memory being read-only.
+    ///
+    /// @param address [u32]: address to write, aligned to 4
+    /// @param data [u32]: word to write
+    #[inline(always)]
+    pub fn write32(&mut self, address: u32, data: u32) {
+        let offset = self.offset(address & !3, 4);
+        // SAFETY: `offset` validated the four bytes to be inside the buffer
+        unsafe { *(self.data.as_mut_ptr().add(offset) as *mut [u8; 4]) = data.to_le_bytes() }
     }
 
-    pub fn read_word(&self, address: u32) -> u32 {
-        if address - self.init_address > self.size {
-            panic!(
-                "Address is to valid while accessing {}: {:#08x}",
-                self.name, address
-            );
+    /// Memory::slice_mut
+    ///
+    /// @param address [u32]: address of the first byte
+    /// @param len [u32]: number of bytes
+    /// @return [&mut [u8]]: content of the memory in the range
+    pub fn slice_mut(&mut self, address: u32, len: u32) -> &mut [u8] {
+        if len == 0 {
+            return &mut [];
         }
+        let offset = self.offset(address, len);
+        &mut self.data[offset..offset + len as usize]
+    }
 
-        self.data[((address - self.init_address) >> 2) as usize]
+    /// Memory::as_bytes
+    ///
+    /// @return [&[u8]]: whole content of the memory
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
     }
 
-    pub fn write(&mut self, address: u32, data: u32, mas: TransferSize) {
-        if address - self.init_address > self.size {
-            panic!(
-                "Address is to valid while accessing {}: {:#08x}",
-                self.name, address
-            );
-        }
+    /// Memory::as_bytes_mut
+    ///
+    /// @return [&mut [u8]]: whole content of the memory
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    /// Memory::read
+    ///
+    /// The size of the transfer is ignored: the whole aligned word is returned, and the cpu
+    /// extracts the byte or halfword it requires.
+    ///
+    /// @param address [u32]: address to read
+    /// @param _mas [TransferSize]: size of the transfer
+    /// @return [u32]: word containing `address`
+    pub fn read(&self, address: u32, _mas: TransferSize) -> u32 {
+        self.read32(address)
+    }
+
+    pub fn read_byte(&self, address: u32) -> u32 {
+        self.read8(address) as u32
+    }
 
+    pub fn read_halfword(&self, address: u32) -> u32 {
+        self.read16(address) as u32
+    }
+
+    pub fn read_word(&self, address: u32) -> u32 {
+        self.read32(address)
+    }
+
+    /// Memory::write
+    ///
+    /// Write the bus data to the memory. For byte and halfword transfers, the data is taken from
+    /// the lane of the bus corresponding to `address`.
+    ///
+    /// @param address [u32]: address to write
+    /// @param data [u32]: data on the bus
+    /// @param mas [TransferSize]: size of the transfer
+    pub fn write(&mut self, address: u32, data: u32, mas: TransferSize) {
         if self.is_read_only {
             println!(
                 "Cannot write on read-only memory {}! Address: {:#010X}; Data: {:#010X}",
@@ -92,45 +189,65 @@ impl Memory {
         }
 
         match mas {
-            TransferSize::BYTE => {
-                let offset = address % 4;
-                let mut data_to_write = self.data[((address - self.init_address) >> 2) as usize];
-                let mask = 0x000000ff << offset * 8;
-                data_to_write &= !mask;
-                data_to_write |= data & mask;
-                self.data[((address - self.init_address) >> 2) as usize] = data_to_write;
-            }
-            TransferSize::HALFWORD => {
-                let offset = address.is_bit_set(1) as u32;
-                let mut data_to_write = self.data[((address - self.init_address) >> 2) as usize];
-                let mask = 0x0000ffff << (offset * 16);
-                data_to_write &= !mask;
-                data_to_write |= data & mask;
-                self.data[((address - self.init_address) >> 2) as usize] = data_to_write;
-            }
-            TransferSize::WORD => {
-                self.data[((address - self.init_address) >> 2) as usize] = data;
-            }
+            TransferSize::BYTE => self.write8(address, (data >> ((address & 3) * 8)) as u8),
+            TransferSize::HALFWORD => self.write16(address, (data >> ((address & 2) * 8)) as u16),
+            TransferSize::WORD => self.write32(address, data),
         }
     }
 
+    /// Memory::reset
+    ///
+    /// Clear the content of the memory. When a seed is provided, the memory is filled with
+    /// pseudo-random bytes instead, so that reads of uninitialized locations can be spotted.
+    /// Different memories get different content out of the same seed.
+    ///
+    /// @param seed [Option<u64>]: seed of the random content, if any
+    pub fn reset(&mut self, seed: Option<u64>) {
+        let Some(seed) = seed else {
+            self.data.fill(0);
+            return;
+        };
+
+        // splitmix64, seeded with the position of the memory in the address space
+        let mut state = seed ^ ((self.init_address as u64) << 32);
+        for chunk in self.data.chunks_mut(8) {
+            state = state.wrapping_add(0x9e3779b97f4a7c15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+            z ^= z >> 31;
+            chunk.copy_from_slice(&z.to_le_bytes()[..chunk.len()]);
+        }
+    }
+
+    /// Memory::size
+    ///
+    /// @return [u32]: number of bytes of the memory
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// Memory::init_from_file
+    ///
+    /// Initialize the content of the memory with the content of a file. If the file is larger
+    /// than the memory, the memory is enlarged so that it can contain the whole file: this allows
+    /// to size the storage of the game pak ROM to the loaded image.
+    ///
+    /// @param file_name [&String]: file to load
     pub fn init_from_file(&mut self, file_name: &String) {
         let mut f =
             File::open(&file_name).expect("Unable to load file while initializing {self.name}");
         let metadata = std::fs::metadata(&file_name)
             .expect("Unable to read metadata while initializing {self.name}");
 
-        let mut buffer: Vec<u8> = vec![0; metadata.len().next_multiple_of(4) as usize];
-        f.read(&mut buffer)
-            .expect("Buffer overflow while initializing {self.name}");
-
-        let mut index: u32 = 0;
-        while (index as usize) < buffer.len() {
-            let mut rdr = Cursor::new(&buffer[(index as usize)..(index.wrapping_add(4) as usize)]);
-            let data = rdr.read_u32::<LittleEndian>().unwrap();
-            self.data[(index >> 2) as usize] = data;
-            index += 4;
+        let file_size = metadata.len() as usize;
+        if file_size.next_multiple_of(4) > self.data.len() {
+            self.data.resize(file_size.next_multiple_of(4), 0);
+            self.size = self.data.len() as u32;
         }
+
+        f.read_exact(&mut self.data[..file_size])
+            .expect("Buffer overflow while initializing {self.name}");
     }
 }
 
@@ -160,3 +277,77 @@ fn test_memory() {
     assert_eq!(memory.read_halfword(6), 0x4567);
     assert_eq!(memory.read_word(6), 0x45671200);
 }
+
+#[test]
+fn test_memory_init_from_file() {
+    let file_name = std::env::temp_dir().join("crusty_gba_test_memory_init.bin");
+    std::fs::write(&file_name, [0x11, 0x22, 0x33, 0x44, 0x55, 0x66]).unwrap();
+    let file_name = String::from(file_name.to_str().unwrap());
+
+    // The memory grows to contain the file
+    let mut memory = Memory::new(0x08000000, 0, true, String::from("test rom"));
+    memory.init_from_file(&file_name);
+    assert_eq!(memory.size(), 8);
+    assert_eq!(memory.read_word(0x08000000), 0x44332211);
+    assert_eq!(memory.read_word(0x08000004), 0x00006655);
+
+    // A larger memory keeps its size
+    let mut memory = Memory::new(0, 0x4000, true, String::from("test bios"));
+    memory.init_from_file(&file_name);
+    assert_eq!(memory.size(), 0x4000);
+    assert_eq!(memory.read_halfword(4), 0x6655);
+
+    let _ = std::fs::remove_file(&file_name);
+}
+
+#[test]
+fn test_memory_typed_accessors() {
+    let mut memory = Memory::new(0x03000000, 0x10, false, String::from("test memory"));
+
+    memory.write32(0x03000000, 0x44332211);
+    assert_eq!(memory.read8(0x03000000), 0x11);
+    assert_eq!(memory.read8(0x03000003), 0x44);
+    assert_eq!(memory.read16(0x03000002), 0x4433);
+    assert_eq!(memory.read16(0x03000003), 0x4433);
+    assert_eq!(memory.read32(0x03000002), 0x44332211);
+
+    memory.write16(0x0300000f, 0xbeef);
+    memory.write8(0x0300000d, 0xaa);
+    assert_eq!(memory.read32(0x0300000c), 0xbeefaa00);
+
+    // Bulk accessors
+    memory
+        .slice_mut(0x03000004, 4)
+        .copy_from_slice(&[1, 2, 3, 4]);
+    assert_eq!(memory.read32(0x03000004), 0x04030201);
+    assert_eq!(&memory.as_bytes()[2..5], &[0x33, 0x44, 0x01]);
+    assert_eq!(memory.as_bytes().len(), 0x10);
+}
+
+#[test]
+#[should_panic]
+fn test_memory_out_of_range() {
+    let memory = Memory::new(0x03000000, 0x10, false, String::from("test memory"));
+    memory.read32(0x02fffffc);
+}
+
+#[test]
+fn test_memory_reset() {
+    let mut memory = Memory::new(0x02000000, 0x100, false, String::from("test memory"));
+
+    memory.reset(Some(1234));
+    let first = memory.as_bytes().to_vec();
+    assert!(first.iter().any(|&b| b != 0));
+
+    // The same seed gives the same content, different seeds and memories different content
+    memory.reset(Some(1234));
+    assert_eq!(memory.as_bytes(), &first[..]);
+    memory.reset(Some(4321));
+    assert_ne!(memory.as_bytes(), &first[..]);
+    let mut other = Memory::new(0x03000000, 0x100, false, String::from("test memory"));
+    other.reset(Some(1234));
+    assert_ne!(other.as_bytes(), &first[..]);
+
+    memory.reset(None);
+    assert!(memory.as_bytes().iter().all(|&b| b == 0));
+}